@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use serde_derive::{Serialize,Deserialize};
+
+use crate::fields::{FieldLevels, FIELDS};
+use crate::policy::{DecisionFactor, Direction, Observation};
+use crate::simulation::{Animal, Environment, EnvironmentTag, RESOURCES, animal_distance, distance};
+
+// 把视野内的环境压缩成决策树的状态 key.
+// Raw 每个可见环境一条 DistanceDirection, 几乎每个 tick 都是新的 key;
+// Summary 只保留每个方向上每种环境最近的一个, 每种环境的数量和 hp 档位, 学到的权重才能被反复用到.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum StateAbstraction {
+    Raw,
+    Summary {
+        // hp 按这个宽度分档, 0 表示保留精确的 hp
+        hp_bucket : u32,
+        // 每种环境的数量最多数到这里, 0 表示不统计数量
+        count_cap : u32,
+    },
+}
+
+impl Default for StateAbstraction {
+    fn default() -> Self {
+        StateAbstraction::Summary {
+            hp_bucket : 5,
+            count_cap : 3,
+        }
+    }
+}
+
+impl StateAbstraction {
+    // 旧的 JSON 没有这个字段, 它们的 key 都是 Raw 生成的.
+    pub fn raw() -> Self {
+        StateAbstraction::Raw
+    }
+
+    pub fn summarize(&self, a: &Animal, observation : Observation) -> Vec<DecisionFactor> {
+        let Observation { environments : ve, animals : va, fields, treasury, .. } = observation;
+        match self {
+            StateAbstraction::Raw => {
+                let mut vdf = summarize_blocked(a, &ve);
+                vdf.extend(summarize_stock(a, 0));
+                vdf.extend(summarize_fields(&fields));
+                vdf.extend(summarize_treasury(treasury));
+                vdf.extend(summarize_raw(a, ve));
+                vdf.extend(summarize_animals_raw(a, va));
+                vdf
+            },
+            StateAbstraction::Summary { hp_bucket, count_cap } => {
+                let mut vdf = summarize_blocked(a, &ve);
+                vdf.extend(summarize_stock(a, *count_cap));
+                vdf.extend(summarize_fields(&fields));
+                vdf.extend(summarize_treasury(treasury));
+                vdf.extend(summarize_nearest(a, ve, *hp_bucket, *count_cap));
+                vdf.extend(summarize_animals_nearest(a, va, *count_cap));
+                vdf
+            },
+        }
+    }
+}
+
+pub fn direction_of(e : &Environment, a : &Animal) -> Direction {
+    direction_between(e.position, a.position)
+}
+
+// target 在 from 的哪个方向.
+pub fn direction_between(target : (i32, i32), from : (i32, i32)) -> Direction {
+    if i32::abs(target.0 - from.0) > i32::abs(target.1 - from.1) {
+        if target.0 > from.0 {
+            Direction::Up
+        } else {
+            Direction::Dowm
+        }
+    } else {
+        if target.1 > from.1 {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    }
+}
+
+// 上下左右相邻的格子里有没有挡路的环境; 障碍物本身也照常按 OBSTACLE 出现在其它 factor 里.
+// 选中的蓝图也放在这里, 两种 abstraction 都一样.
+fn summarize_blocked(a: &Animal, ve : &[Environment]) -> Vec<DecisionFactor> {
+    let mut blocked : Vec<Direction> = ve.iter()
+        .filter(|e| e.alive && e.blocking && distance(e, a) == 1)
+        .map(|e| direction_of(e, a))
+        .collect();
+    blocked.sort();
+    blocked.dedup();
+    let mut vdf : Vec<DecisionFactor> = blocked.into_iter().map(DecisionFactor::Blocked).collect();
+    if a.blueprint != 0 {
+        vdf.push(DecisionFactor::Blueprint(a.blueprint));
+    }
+    vdf
+}
+
+// 背包里有的资源, cap 为 0 时保留精确的数量.
+fn summarize_stock(a: &Animal, cap : u32) -> Vec<DecisionFactor> {
+    RESOURCES.iter()
+        .map(|r| (*r, a.inventory.get(*r)))
+        .filter(|(_, n)| *n > 0)
+        .map(|(r, n)| DecisionFactor::Stock(r, if cap > 0 { u32::min(n, cap) } else { n }))
+        .collect()
+}
+
+// 脚下格子的场; 档位已经按 level_width 分过, 不再用 count_cap 截断.
+fn summarize_fields(fields : &FieldLevels) -> Vec<DecisionFactor> {
+    FIELDS.iter()
+        .map(|f| (*f, fields.get(*f)))
+        .filter(|(_, level)| *level > 0)
+        .map(|(f, level)| DecisionFactor::FieldLevel(f, level))
+        .collect()
+}
+
+// 国库的档位已经按 level_width 分过, 不再用 count_cap 截断.
+fn summarize_treasury(treasury : Option<i32>) -> Option<DecisionFactor> {
+    treasury.map(DecisionFactor::Treasury)
+}
+
+fn summarize_raw(a: &Animal, ve : Vec<Environment>) -> Vec<DecisionFactor> {
+    let mut vdf = vec![];
+    for e in ve {
+        if e.position == a.position {
+            vdf.push(DecisionFactor::CurrentLocation(e.tag));
+            continue;
+        }
+        let dis = distance(&e, a);
+        vdf.push(DecisionFactor::DistanceDirection(dis as u32, direction_of(&e, a), e.tag));
+    }
+    vdf.push(DecisionFactor::CurrentHp(a.hp));
+    vdf
+}
+
+fn summarize_nearest(a: &Animal, ve : Vec<Environment>, hp_bucket : u32, count_cap : u32) -> Vec<DecisionFactor> {
+    let mut current : Vec<EnvironmentTag> = vec![];
+    let mut nearest : BTreeMap<(Direction, EnvironmentTag), u32> = BTreeMap::new();
+    let mut counts : BTreeMap<EnvironmentTag, u32> = BTreeMap::new();
+    for e in ve {
+        *counts.entry(e.tag).or_insert(0) += 1;
+        if e.position == a.position {
+            if !current.contains(&e.tag) {
+                current.push(e.tag);
+            }
+            continue;
+        }
+        let dis = distance(&e, a) as u32;
+        let entry = nearest.entry((direction_of(&e, a), e.tag)).or_insert(dis);
+        if dis < *entry {
+            *entry = dis;
+        }
+    }
+    current.sort();
+    let mut vdf = vec![];
+    for tag in current {
+        vdf.push(DecisionFactor::CurrentLocation(tag));
+    }
+    for ((dir, tag), dis) in nearest {
+        vdf.push(DecisionFactor::DistanceDirection(dis, dir, tag));
+    }
+    if count_cap > 0 {
+        for (tag, count) in counts {
+            vdf.push(DecisionFactor::TagCount(tag, u32::min(count, count_cap)));
+        }
+    }
+    if hp_bucket > 0 {
+        vdf.push(DecisionFactor::HpBucket(a.hp / hp_bucket as i32));
+    } else {
+        vdf.push(DecisionFactor::CurrentHp(a.hp));
+    }
+    vdf
+}
+
+fn summarize_animals_raw(a: &Animal, va : Vec<Animal>) -> Vec<DecisionFactor> {
+    let mut vdf = vec![];
+    for b in va {
+        let same = b.species == a.species;
+        if b.position == a.position {
+            vdf.push(DecisionFactor::AnimalHere(same));
+            continue;
+        }
+        vdf.push(DecisionFactor::AnimalDirection(animal_distance(&b, a) as u32, direction_between(b.position, a.position), same));
+    }
+    vdf
+}
+
+// 和环境一样: 每个方向上同一物种 / 别的物种各保留最近的一个, 再加上两边的数量.
+fn summarize_animals_nearest(a: &Animal, va : Vec<Animal>, count_cap : u32) -> Vec<DecisionFactor> {
+    let mut here : Vec<bool> = vec![];
+    let mut nearest : BTreeMap<(Direction, bool), u32> = BTreeMap::new();
+    let mut counts : BTreeMap<bool, u32> = BTreeMap::new();
+    for b in va {
+        let same = b.species == a.species;
+        *counts.entry(same).or_insert(0) += 1;
+        if b.position == a.position {
+            if !here.contains(&same) {
+                here.push(same);
+            }
+            continue;
+        }
+        let dis = animal_distance(&b, a) as u32;
+        let entry = nearest.entry((direction_between(b.position, a.position), same)).or_insert(dis);
+        if dis < *entry {
+            *entry = dis;
+        }
+    }
+    here.sort();
+    let mut vdf = vec![];
+    for same in here {
+        vdf.push(DecisionFactor::AnimalHere(same));
+    }
+    for ((dir, same), dis) in nearest {
+        vdf.push(DecisionFactor::AnimalDirection(dis, dir, same));
+    }
+    if count_cap > 0 {
+        for (same, count) in counts {
+            vdf.push(DecisionFactor::AnimalCount(same, u32::min(count, count_cap)));
+        }
+    }
+    vdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::{blank_animal, blank_environment};
+
+    fn obstacle(pos : (i32, i32), blocking : bool, alive : bool) -> Environment {
+        let mut e = blank_environment(pos);
+        e.blocking = blocking;
+        e.alive = alive;
+        e
+    }
+
+    #[test]
+    fn only_adjacent_live_obstacles_are_blocked_factors() {
+        let a = blank_animal((5, 5));
+        let ve = vec![
+            obstacle((6, 5), true, true),
+            obstacle((5, 4), true, true),
+            obstacle((5, 7), true, true),
+            obstacle((4, 5), false, true),
+            obstacle((5, 6), true, false),
+        ];
+        for abstraction in [StateAbstraction::Raw, StateAbstraction::default()] {
+            let blocked : Vec<DecisionFactor> = abstraction.summarize(&a, Observation { environments : ve.clone(), ..Observation::default() })
+                .into_iter()
+                .filter(|f| matches!(f, DecisionFactor::Blocked(_)))
+                .collect();
+            assert_eq!(blocked, vec![DecisionFactor::Blocked(Direction::Up), DecisionFactor::Blocked(Direction::Left)]);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use serde_derive::{Serialize,Deserialize};
+
+use crate::simulation::Environment;
+
+// 城市的预算: 每个 tick 起作用的建筑交 tax, 所有建筑付 upkeep, Build 时从国库里扣模板的 price.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    pub initial_treasury : i32,
+    // 余额低于这个值就破产, 这一局结束
+    pub bankruptcy_threshold : i32,
+    // 动物感觉到的档位 = 余额 / level_width
+    pub level_width : i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Treasury {
+    // 世界配置里没有 budget 时不收税, 不付钱, 也不会破产
+    pub enabled : bool,
+    pub balance : i32,
+    pub bankrupt : bool,
+}
+
+impl Treasury {
+    pub fn new(config : &Option<BudgetConfig>) -> Self {
+        Treasury {
+            enabled : config.is_some(),
+            balance : config.as_ref().map_or(0, |c| c.initial_treasury),
+            bankrupt : false,
+        }
+    }
+
+    pub fn can_afford(&self, price : i32) -> bool {
+        !self.enabled || self.balance >= price
+    }
+
+    pub fn pay(&mut self, price : i32) {
+        if self.enabled {
+            self.balance -= price;
+        }
+    }
+
+    // 动物感觉到的余额档位, 世界配置里没有 budget 时为 None.
+    pub fn level(&self, config : &Option<BudgetConfig>) -> Option<i32> {
+        config.as_ref().map(|c| self.balance / c.level_width)
+    }
+}
+
+// 在每个 tick 的末尾, update_fields 之后调用: 结算税收和维护费, 再检查破产.
+pub fn settle_budget(treasury : &mut Treasury, ve : &[Environment], config : &Option<BudgetConfig>) {
+    let config = match config {
+        Some(config) => config,
+        None => return,
+    };
+    for e in ve.iter().filter(|e| e.alive) {
+        if e.functioning() {
+            treasury.balance += e.tax;
+        }
+        treasury.balance -= e.upkeep;
+    }
+    treasury.bankrupt = treasury.balance < config.bankruptcy_threshold;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::blank_environment;
+
+    fn building(tax : i32, upkeep : i32) -> Environment {
+        let mut e = blank_environment((0, 0));
+        e.tax = tax;
+        e.upkeep = upkeep;
+        e
+    }
+
+    fn budget(initial_treasury : i32, bankruptcy_threshold : i32) -> Option<BudgetConfig> {
+        Some(BudgetConfig { initial_treasury, bankruptcy_threshold, level_width : 10 })
+    }
+
+    #[test]
+    fn tax_only_from_functioning_buildings_and_upkeep_from_all_alive() {
+        let mut unconnected = building(5, 2);
+        unconnected.requires_road = true;
+        let mut dead = building(5, 10);
+        dead.alive = false;
+        let ve = vec![building(3, 1), unconnected, dead];
+        let config = budget(20, 0);
+        let mut treasury = Treasury::new(&config);
+        settle_budget(&mut treasury, &ve, &config);
+        assert_eq!(treasury.balance, 20);
+        assert!(!treasury.bankrupt);
+        assert_eq!(treasury.level(&config), Some(2));
+    }
+
+    #[test]
+    fn falling_below_the_threshold_is_bankruptcy() {
+        let ve = vec![building(0, 3)];
+        let config = budget(5, 0);
+        let mut treasury = Treasury::new(&config);
+        settle_budget(&mut treasury, &ve, &config);
+        assert_eq!(treasury.balance, 2);
+        assert!(!treasury.bankrupt);
+        settle_budget(&mut treasury, &ve, &config);
+        assert_eq!(treasury.balance, -1);
+        assert!(treasury.bankrupt);
+        assert!(!treasury.can_afford(1));
+    }
+
+    #[test]
+    fn without_a_budget_nothing_is_settled() {
+        let ve = vec![building(0, 100)];
+        let mut treasury = Treasury::new(&None);
+        settle_budget(&mut treasury, &ve, &None);
+        treasury.pay(50);
+        assert_eq!(treasury.balance, 0);
+        assert!(!treasury.bankrupt);
+        assert!(treasury.can_afford(1000));
+        assert_eq!(treasury.level(&None), None);
+    }
+}
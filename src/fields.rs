@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use serde_derive::{Serialize,Deserialize};
+
+use crate::roads::NEIGHBOURS;
+use crate::simulation::Environment;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    Pollution,
+    LandValue,
+    Crime,
+}
+
+pub const FIELDS : [Field; 3] = [Field::Pollution, Field::LandValue, Field::Crime];
+
+// 每个 tick 每个格子先向上下左右邻居的平均值靠近 diffusion, 再衰减 decay, 最后加上这个格子上环境的 emits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FieldConfig {
+    pub diffusion : f64,
+    pub decay : f64,
+    // 动物感觉到的档位 = 值 / level_width
+    pub level_width : f64,
+}
+
+// 动物脚下格子的场的档位, 做决定时放在 Observation 里交给策略.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FieldLevels {
+    pub pollution : u32,
+    pub land_value : u32,
+    pub crime : u32,
+}
+
+impl FieldLevels {
+    pub fn get(&self, f : Field) -> u32 {
+        match f {
+            Field::Pollution => self.pollution,
+            Field::LandValue => self.land_value,
+            Field::Crime => self.crime,
+        }
+    }
+}
+
+// 每种场一张地图大小的网格, 按 x * height + y 排.
+#[derive(Clone, Debug)]
+pub struct ScalarFields {
+    pub map_size : (i32, i32),
+    pub values : BTreeMap<Field, Vec<f64>>,
+}
+
+impl ScalarFields {
+    pub fn new(map_size : (i32, i32)) -> Self {
+        let cells = (map_size.0 * map_size.1) as usize;
+        ScalarFields {
+            map_size,
+            values : FIELDS.iter().map(|f| (*f, vec![0.0; cells])).collect(),
+        }
+    }
+
+    fn index(&self, pos : (i32, i32)) -> Option<usize> {
+        if pos.0 < 0 || pos.1 < 0 || pos.0 >= self.map_size.0 || pos.1 >= self.map_size.1 {
+            return None;
+        }
+        Some((pos.0 * self.map_size.1 + pos.1) as usize)
+    }
+
+    pub fn get(&self, f : Field, pos : (i32, i32)) -> f64 {
+        self.index(pos).map_or(0.0, |i| self.values[&f][i])
+    }
+
+    pub fn level(&self, f : Field, pos : (i32, i32), config : &FieldConfig) -> u32 {
+        (self.get(f, pos) / config.level_width) as u32
+    }
+
+    // 只有起作用的环境才 emits.
+    pub fn update(&mut self, ve : &[Environment], config : &FieldConfig) {
+        for f in FIELDS {
+            let old = &self.values[&f];
+            let mut next = old.clone();
+            for x in 0..self.map_size.0 {
+                for y in 0..self.map_size.1 {
+                    let i = self.index((x, y)).unwrap();
+                    let neighbours : Vec<f64> = NEIGHBOURS.iter()
+                        .filter_map(|inc| self.index((x + inc.0, y + inc.1)))
+                        .map(|j| old[j])
+                        .collect();
+                    // 没有邻居 (1x1 的地图) 时不扩散
+                    let mean = if neighbours.is_empty() { old[i] } else { neighbours.iter().sum::<f64>() / neighbours.len() as f64 };
+                    next[i] = (old[i] + config.diffusion * (mean - old[i])) * (1.0 - config.decay);
+                }
+            }
+            for e in ve.iter().filter(|e| e.alive && e.functioning()) {
+                if let Some(i) = self.index(e.position) {
+                    next[i] += e.emits.iter().filter(|(ef, _)| *ef == f).map(|(_, amount)| amount).sum::<f64>();
+                }
+            }
+            self.values.insert(f, next);
+        }
+    }
+
+    // pos 这个格子上每种场的档位; 世界配置里没有 fields 时都是 0.
+    pub fn levels(&self, pos : (i32, i32), config : &Option<FieldConfig>) -> FieldLevels {
+        match config {
+            Some(config) => FieldLevels {
+                pollution : self.level(Field::Pollution, pos, config),
+                land_value : self.level(Field::LandValue, pos, config),
+                crime : self.level(Field::Crime, pos, config),
+            },
+            None => FieldLevels::default(),
+        }
+    }
+}
+
+// 在每个 tick 的末尾, grow_zones 之后调用; 世界配置里没有 fields 时什么都不做.
+pub fn update_fields(fields : &mut ScalarFields, ve : &[Environment], config : &Option<FieldConfig>) {
+    if let Some(config) = config {
+        fields.update(ve, config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::blank_environment;
+
+    fn emitter(pos : (i32, i32), emits : Vec<(Field, f64)>) -> Environment {
+        let mut e = blank_environment(pos);
+        e.emits = emits;
+        e
+    }
+
+    fn config(diffusion : f64, decay : f64) -> FieldConfig {
+        FieldConfig { diffusion, decay, level_width : 1.0 }
+    }
+
+    fn close(a : f64, b : f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn single_cell_only_decays() {
+        let mut fields = ScalarFields::new((1, 1));
+        fields.values.get_mut(&Field::Crime).unwrap()[0] = 4.0;
+        fields.update(&[], &config(0.5, 0.25));
+        assert!(close(fields.get(Field::Crime, (0, 0)), 3.0));
+    }
+
+    #[test]
+    fn diffusion_moves_towards_the_neighbour_mean() {
+        let mut fields = ScalarFields::new((3, 3));
+        let centre = fields.index((1, 1)).unwrap();
+        fields.values.get_mut(&Field::Pollution).unwrap()[centre] = 8.0;
+        fields.update(&[], &config(0.5, 0.0));
+        assert!(close(fields.get(Field::Pollution, (1, 1)), 4.0));
+        // (1, 0) 有三个邻居, 其中一个是中心
+        assert!(close(fields.get(Field::Pollution, (1, 0)), 0.5 * 8.0 / 3.0));
+        assert!(close(fields.get(Field::Pollution, (0, 0)), 0.0));
+        assert!(close(fields.get(Field::LandValue, (1, 1)), 0.0));
+    }
+
+    #[test]
+    fn only_functioning_environments_emit() {
+        let mut unconnected = emitter((1, 0), vec![(Field::Pollution, 2.0)]);
+        unconnected.requires_road = true;
+        let mut dead = emitter((2, 0), vec![(Field::Pollution, 2.0)]);
+        dead.alive = false;
+        let ve = vec![emitter((0, 0), vec![(Field::Pollution, 2.0), (Field::LandValue, 1.0)]), unconnected, dead];
+        let mut fields = ScalarFields::new((3, 1));
+        fields.update(&ve, &config(0.0, 0.0));
+        assert!(close(fields.get(Field::Pollution, (0, 0)), 2.0));
+        assert!(close(fields.get(Field::LandValue, (0, 0)), 1.0));
+        assert!(close(fields.get(Field::Pollution, (1, 0)), 0.0));
+        assert!(close(fields.get(Field::Pollution, (2, 0)), 0.0));
+        assert_eq!(fields.levels((0, 0), &Some(config(0.0, 0.0))).pollution, 2);
+    }
+}
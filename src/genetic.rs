@@ -0,0 +1,134 @@
+use crate::policy::{policy_from_json, policy_to_json, Policy, PolicyError};
+use crate::training::decision_making_single_loop;
+use crate::world::WorldConfig;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Selection {
+    // 随机抽 n 个, 取活得最久的
+    Tournament(u32),
+    // 按存活 tick 数成比例抽取
+    Roulette,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GeneticConfig {
+    pub population_size : u32,
+    pub generations : u32,
+    // 每代直接保留到下一代的最好个体数
+    pub elitism : u32,
+    pub selection : Selection,
+    pub mutate_factor : u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GenerationStats {
+    pub generation : u32,
+    pub best : u128,
+    pub mean : f64,
+    pub worst : u128,
+}
+
+fn select<'a, P>(scored : &'a [(P, u128)], selection : Selection, rng : &mut oorandom::Rand32) -> &'a P {
+    match selection {
+        Selection::Tournament(size) => {
+            let mut best = &scored[rng.rand_range(0..scored.len() as u32) as usize];
+            for _ in 1..size {
+                let challenger = &scored[rng.rand_range(0..scored.len() as u32) as usize];
+                if challenger.1 > best.1 {
+                    best = challenger;
+                }
+            }
+            &best.0
+        },
+        Selection::Roulette => {
+            let total : u128 = scored.iter().map(|(_, tick)| *tick).sum();
+            if total == 0 {
+                return &scored[rng.rand_range(0..scored.len() as u32) as usize].0;
+            }
+            let mut rand = (rng.rand_float() as f64 * total as f64) as u128;
+            for (p, tick) in scored {
+                if rand < *tick {
+                    return p;
+                }
+                rand -= tick;
+            }
+            &scored[scored.len() - 1].0
+        },
+    }
+}
+
+fn evaluate_population<P: Policy + 'static>(population : Vec<P>, _world_config : &WorldConfig) -> Vec<(P, u128)> {
+    let mut scored = vec![];
+    for mut p in population {
+        p.clear_history();
+        scored.push(decision_making_single_loop(false, p, _world_config));
+    }
+    // 稳定排序, 同分的个体保持原来的顺序
+    scored.sort_by_key(|s| std::cmp::Reverse(s.1));
+    scored
+}
+
+fn generation_stats<P>(generation : u32, scored : &[(P, u128)]) -> GenerationStats {
+    let total : u128 = scored.iter().map(|(_, tick)| *tick).sum();
+    GenerationStats {
+        generation,
+        best : scored.first().map_or(0, |s| s.1),
+        mean : total as f64 / scored.len().max(1) as f64,
+        worst : scored.last().map_or(0, |s| s.1),
+    }
+}
+
+fn breed<P: Policy>(scored : &[(P, u128)], _config : &GeneticConfig, population_size : usize, rng : &mut oorandom::Rand32) -> Vec<P> {
+    let mut next : Vec<P> = scored.iter()
+        .take(_config.elitism as usize)
+        .map(|(p, _)| p.clone())
+        .collect();
+    while next.len() < population_size {
+        let a = select(scored, _config.selection, rng);
+        let b = select(scored, _config.selection, rng);
+        next.push(a.crossover(b, rng).mutate(_config.mutate_factor, rng));
+    }
+    next
+}
+
+// 一个持续存在的种群, 每代: 评估, 精英保留, 选择两个父代交叉再变异, 直到种群补满.
+// 种群可以从 _population_json 恢复, 结束时存回去; 最好的个体存到 _to_json.
+pub fn genetic_run<P: Policy + 'static>(
+    _config : GeneticConfig,
+    _world_config : &WorldConfig,
+    _initial_policy : P,
+    _population_json : Option<String>,
+    _to_json : Option<String>) -> Result<Vec<GenerationStats>, PolicyError> {
+    let evolver_seed = 64;
+    let mut rng_evolver = oorandom::Rand32::new(evolver_seed);
+    let population_size = _config.population_size.max(1) as usize;
+    let mut population : Vec<P> = match _population_json.clone().map(policy_from_json::<Vec<P>>) {
+        Some(Ok(population)) if !population.is_empty() => population,
+        Some(Err(PolicyError::MissingFile(_))) | Some(Ok(_)) | None => vec![_initial_policy],
+        Some(Err(err)) => return Err(err),
+    };
+    while population.len() < population_size {
+        let seed = &population[rng_evolver.rand_range(0..population.len() as u32) as usize];
+        let child = seed.mutate(_config.mutate_factor, &mut rng_evolver);
+        population.push(child);
+    }
+    let mut stats = vec![];
+    let mut scored = evaluate_population(population, _world_config);
+    for generation in 0.._config.generations {
+        if generation > 0 {
+            scored = evaluate_population(breed(&scored, &_config, population_size, &mut rng_evolver), _world_config);
+        }
+        let generation_stats = generation_stats(generation, &scored);
+        println!("GENERATION {:?} BEST {:?} MEAN {:.2} WORST {:?}",
+            generation, generation_stats.best, generation_stats.mean, generation_stats.worst);
+        stats.push(generation_stats);
+    }
+    let population : Vec<P> = scored.into_iter().map(|(p, _)| p).collect();
+    if let Some(json_path) = _population_json {
+        policy_to_json(&population, json_path)?;
+    }
+    if let Some(json_path) = _to_json {
+        population[0].to_json(json_path)?;
+    }
+    Ok(stats)
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+use serde_derive::{Serialize,Deserialize};
+
+use crate::abstraction::StateAbstraction;
+use crate::policy::{
+    canonical_key, policy_from_json, policy_to_json,
+    Decision, DecisionMaker, DecisionMakingTree, Observation, PolicyError, StateKey,
+};
+use crate::simulation::Animal;
+
+// 人玩的记录, 和 decision_history 同样的形状; 多局连在一起, tick 归零处是新的一局.
+// key 用记录时的 abstraction 生成, 克隆出的决策树也用同一个.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HumanTrace {
+    pub abstraction : StateAbstraction,
+    pub decision_history : Vec<(u128, StateKey, Decision)>,
+}
+
+impl HumanTrace {
+    pub fn new(abstraction : StateAbstraction) -> Self {
+        HumanTrace {
+            abstraction,
+            decision_history : vec![],
+        }
+    }
+
+    pub fn from_json(_path_name: String) -> Result<HumanTrace, PolicyError> {
+        policy_from_json(_path_name)
+    }
+
+    // 已有的记录文件接着往后记, 没有的从空记录开始.
+    pub fn resume(_path_name: &str, abstraction : StateAbstraction) -> Result<HumanTrace, PolicyError> {
+        if Path::new(_path_name).exists() {
+            HumanTrace::from_json(_path_name.to_string())
+        } else {
+            Ok(HumanTrace::new(abstraction))
+        }
+    }
+
+    pub fn to_json(&self, json_path:String) -> Result<(), PolicyError> {
+        policy_to_json(self, json_path)
+    }
+
+    pub fn record(&mut self, tick : u128, a : &Animal, observation : Observation, decision : Decision) {
+        let key = canonical_key(self.abstraction.summarize(a, observation));
+        self.decision_history.push((tick, key, decision));
+    }
+
+    pub fn episode_count(&self) -> usize {
+        self.decision_history.iter().filter(|(tick, _, _)| *tick == 0).count()
+    }
+}
+
+// 行为克隆: 每个记录过的状态从全 1 的权重开始, 人每做一次某个决定就给它加 clone_factor.
+// 没见过的状态仍然用 DecisionMaker::default, 见过的状态也保留一点探索.
+pub fn behavior_cloning(traces : &[HumanTrace], clone_factor : u32) -> DecisionMakingTree {
+    let abstraction = traces.first().map(|t| t.abstraction.clone()).unwrap_or_default();
+    let mut tree = DecisionMakingTree::with_abstraction(abstraction);
+    // abstraction 不同的记录 key 对不上, 不参与克隆.
+    for trace in traces.iter().filter(|t| t.abstraction == tree.abstraction) {
+        for (_, key, decision) in &trace.decision_history {
+            let dm = tree.decision_chain.entry(key.clone()).or_insert_with(DecisionMaker::uniform);
+            dm.increase_chance(*decision, clone_factor);
+        }
+    }
+    tree
+}
@@ -0,0 +1,360 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use serde_derive::{Serialize,Deserialize};
+
+use crate::abstraction::StateAbstraction;
+use crate::fields::{Field, FIELDS};
+use crate::policy::{canonical_key, Decision, DecisionFactor, Direction, Observation, Outcome, Policy, StateKey, DECISIONS};
+use crate::simulation::{Animal, EnvironmentTag, Resource, RESOURCES};
+
+// 看不到某种环境时的距离
+const FAR : i32 = 1000;
+
+const TAGS : [EnvironmentTag; 7] = [
+    EnvironmentTag::DANGER,
+    EnvironmentTag::CHALLENGE,
+    EnvironmentTag::SHELTER,
+    EnvironmentTag::DEFAULT,
+    EnvironmentTag::OBSTACLE,
+    EnvironmentTag::ROAD,
+    EnvironmentTag::UTILITY,
+];
+
+const DIRECTIONS : [Direction; 4] = [
+    Direction::Up,
+    Direction::Dowm,
+    Direction::Right,
+    Direction::Left,
+];
+
+// 决策树分裂用的特征, 都从 StateKey 里读出来.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Feature {
+    // 最近的某种环境的距离
+    Nearest(EnvironmentTag),
+    // 某个方向上最近的某种环境的距离
+    NearestInDirection(Direction, EnvironmentTag),
+    // 当前格子上有没有某种环境, 1 或 0
+    At(EnvironmentTag),
+    // 视野内某种环境的数量
+    Count(EnvironmentTag),
+    // CurrentHp 或者 HpBucket 的值, 取决于 abstraction
+    Hp,
+    // 这个方向上相邻的格子是否被挡住, 1 或 0
+    Blocked(Direction),
+    // 背包里某种资源的数量
+    Stock(Resource),
+    // 脚下格子某种场的档位
+    FieldLevel(Field),
+    // 国库余额的档位
+    Treasury,
+    // 最近的同一物种 (true) / 别的物种 (false) 的动物的距离
+    NearestAnimal(bool),
+    // 同一格里有没有同一物种 / 别的物种的动物, 1 或 0
+    AnimalHere(bool),
+    // 视野内同一物种 / 别的物种的动物数量
+    AnimalCount(bool),
+}
+
+impl Feature {
+    pub fn all() -> Vec<Feature> {
+        let mut features = vec![Feature::Hp];
+        for tag in TAGS {
+            features.push(Feature::Nearest(tag));
+            features.push(Feature::At(tag));
+            features.push(Feature::Count(tag));
+            for dir in DIRECTIONS {
+                features.push(Feature::NearestInDirection(dir, tag));
+            }
+        }
+        for dir in DIRECTIONS {
+            features.push(Feature::Blocked(dir));
+        }
+        for r in RESOURCES {
+            features.push(Feature::Stock(r));
+        }
+        for f in FIELDS {
+            features.push(Feature::FieldLevel(f));
+        }
+        features.push(Feature::Treasury);
+        for same in [true, false] {
+            features.push(Feature::NearestAnimal(same));
+            features.push(Feature::AnimalHere(same));
+            features.push(Feature::AnimalCount(same));
+        }
+        features
+    }
+
+    pub fn value(&self, key : &StateKey) -> i32 {
+        let mut value = match self {
+            Feature::Nearest(_) | Feature::NearestInDirection(_, _) | Feature::NearestAnimal(_) => FAR,
+            _ => 0,
+        };
+        // abstraction 给出的 TagCount / AnimalCount 已经是数好的数量, 有它就不用自己数
+        let mut tag_count = None;
+        for (df, count) in key {
+            match (self, df) {
+                (Feature::Nearest(tag), DecisionFactor::DistanceDirection(dis, _, t)) if t == tag =>
+                    value = i32::min(value, *dis as i32),
+                (Feature::NearestInDirection(dir, tag), DecisionFactor::DistanceDirection(dis, d, t)) if t == tag && d == dir =>
+                    value = i32::min(value, *dis as i32),
+                (Feature::At(tag), DecisionFactor::CurrentLocation(t)) if t == tag => value = 1,
+                (Feature::Count(tag), DecisionFactor::DistanceDirection(_, _, t)) if t == tag => value += *count as i32,
+                (Feature::Count(tag), DecisionFactor::CurrentLocation(t)) if t == tag => value += *count as i32,
+                (Feature::Count(tag), DecisionFactor::TagCount(t, c)) if t == tag => tag_count = Some(*c as i32),
+                (Feature::Hp, DecisionFactor::CurrentHp(hp)) => value = *hp,
+                (Feature::Hp, DecisionFactor::HpBucket(hp)) => value = *hp,
+                (Feature::Blocked(dir), DecisionFactor::Blocked(d)) if d == dir => value = 1,
+                (Feature::Stock(r), DecisionFactor::Stock(s, n)) if s == r => value = *n as i32,
+                (Feature::FieldLevel(f), DecisionFactor::FieldLevel(g, level)) if g == f => value = *level as i32,
+                (Feature::Treasury, DecisionFactor::Treasury(level)) => value = *level,
+                (Feature::NearestAnimal(same), DecisionFactor::AnimalDirection(dis, _, s)) if s == same =>
+                    value = i32::min(value, *dis as i32),
+                (Feature::AnimalHere(same), DecisionFactor::AnimalHere(s)) if s == same => value = 1,
+                (Feature::AnimalCount(same), DecisionFactor::AnimalDirection(_, _, s)) if s == same => value += *count as i32,
+                (Feature::AnimalCount(same), DecisionFactor::AnimalHere(s)) if s == same => value += *count as i32,
+                (Feature::AnimalCount(same), DecisionFactor::AnimalCount(s, c)) if s == same => tag_count = Some(*c as i32),
+                _ => (),
+            }
+        }
+        tag_count.unwrap_or(value)
+    }
+
+    fn describe(&self, threshold : i32) -> String {
+        match self {
+            Feature::Nearest(tag) => format!("nearest {:?} <= {}", tag, threshold),
+            Feature::NearestInDirection(dir, tag) => format!("nearest {:?} {:?} <= {}", tag, dir, threshold),
+            Feature::At(tag) => format!("not standing on {:?}", tag),
+            Feature::Count(tag) => format!("{:?} count <= {}", tag, threshold),
+            Feature::Hp => format!("hp level <= {}", threshold),
+            Feature::Blocked(dir) => format!("{:?} is open", dir),
+            Feature::Stock(r) => format!("{:?} stock <= {}", r, threshold),
+            Feature::FieldLevel(f) => format!("{:?} level <= {}", f, threshold),
+            Feature::Treasury => format!("treasury level <= {}", threshold),
+            Feature::NearestAnimal(same) => format!("nearest {} animal <= {}", species(*same), threshold),
+            Feature::AnimalHere(same) => format!("no {} animal here", species(*same)),
+            Feature::AnimalCount(same) => format!("{} animal count <= {}", species(*same), threshold),
+        }
+    }
+}
+
+fn species(same : bool) -> &'static str {
+    if same { "same species" } else { "other species" }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TreeNode {
+    Leaf {
+        decision : Decision,
+        samples : u32,
+    },
+    // feature 的值 <= threshold 走 yes, 否则走 no
+    Split {
+        feature : Feature,
+        threshold : i32,
+        yes : Box<TreeNode>,
+        no : Box<TreeNode>,
+    },
+}
+
+impl TreeNode {
+    fn leaf<'a>(&'a self, key : &StateKey) -> &'a TreeNode {
+        match self {
+            TreeNode::Leaf { .. } => self,
+            TreeNode::Split { feature, threshold, yes, no } => {
+                if feature.value(key) <= *threshold {
+                    yes.leaf(key)
+                } else {
+                    no.leaf(key)
+                }
+            },
+        }
+    }
+
+    fn render(&self, depth : usize, out : &mut String) {
+        let indent = "    ".repeat(depth);
+        match self {
+            TreeNode::Leaf { decision, samples } => {
+                let _ = writeln!(out, "{}{:?} ({} samples)", indent, decision, samples);
+            },
+            TreeNode::Split { feature, threshold, yes, no } => {
+                let _ = writeln!(out, "{}if {}:", indent, feature.describe(*threshold));
+                yes.render(depth + 1, out);
+                let _ = writeln!(out, "{}else:", indent);
+                no.render(depth + 1, out);
+            },
+        }
+    }
+}
+
+// 一条训练样本: 特征值, 做出的决定, 权重.
+struct Sample {
+    values : Vec<i32>,
+    decision : Decision,
+    weight : f64,
+}
+
+fn weighted_counts(samples : &[&Sample]) -> BTreeMap<Decision, f64> {
+    let mut counts = BTreeMap::new();
+    for s in samples {
+        *counts.entry(s.decision).or_insert(0.0) += s.weight;
+    }
+    counts
+}
+
+fn gini(counts : &BTreeMap<Decision, f64>) -> (f64, f64) {
+    let total : f64 = counts.values().sum();
+    if total <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let impurity = 1.0 - counts.values().map(|c| (c / total) * (c / total)).sum::<f64>();
+    (impurity, total)
+}
+
+fn build(features : &[Feature], samples : Vec<&Sample>, depth : u32, max_depth : u32, min_samples_split : usize) -> TreeNode {
+    let counts = weighted_counts(&samples);
+    let (impurity, total) = gini(&counts);
+    let majority = counts.iter()
+        .fold((Decision::Wait, f64::MIN), |best, (d, c)| if *c > best.1 { (*d, *c) } else { best }).0;
+    let leaf = TreeNode::Leaf { decision : majority, samples : samples.len() as u32 };
+    if depth >= max_depth || samples.len() < min_samples_split || impurity <= 0.0 {
+        return leaf;
+    }
+    let mut best : Option<(usize, i32, f64)> = None;
+    for (fi, _) in features.iter().enumerate() {
+        let mut thresholds : Vec<i32> = samples.iter().map(|s| s.values[fi]).collect();
+        thresholds.sort();
+        thresholds.dedup();
+        thresholds.pop();
+        for threshold in thresholds {
+            let (yes, no) : (Vec<&Sample>, Vec<&Sample>) = samples.iter().partition(|s| s.values[fi] <= threshold);
+            let (yes_impurity, yes_total) = gini(&weighted_counts(&yes));
+            let (no_impurity, no_total) = gini(&weighted_counts(&no));
+            let split_impurity = (yes_impurity * yes_total + no_impurity * no_total) / total;
+            if split_impurity < impurity && best.map_or(true, |b| split_impurity < b.2) {
+                best = Some((fi, threshold, split_impurity));
+            }
+        }
+    }
+    match best {
+        Some((fi, threshold, _)) => {
+            let (yes, no) : (Vec<&Sample>, Vec<&Sample>) = samples.into_iter().partition(|s| s.values[fi] <= threshold);
+            TreeNode::Split {
+                feature : features[fi],
+                threshold,
+                yes : Box::new(build(features, yes, depth + 1, max_depth, min_samples_split)),
+                no : Box::new(build(features, no, depth + 1, max_depth, min_samples_split)),
+            }
+        },
+        None => leaf,
+    }
+}
+
+// 从带结果的决策记录里归纳出一棵真正的决策树.
+// 每局结束后用记录下的样本重新建树: 只学没有让 hp/ability 损失超过一个 tick 消耗的决定,
+// 收益越大权重越高. 没有树或者叶子没有样本时随机探索.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InducedDecisionTree {
+    pub tree : Option<TreeNode>,
+    // (状态, 决定, 这个 tick 的 hp + ability 变化)
+    pub examples : Vec<(StateKey, Decision, i32)>,
+    pub decision_history : Vec<(u128, StateKey, Decision)>,
+    pub abstraction : StateAbstraction,
+    pub max_depth : u32,
+    pub min_samples_split : u32,
+    pub max_examples : u32,
+    pub epsilon : f64,
+    // 每只动物这个 tick 的 (状态, 决定), 等 learn 拿到结果
+    #[serde(skip)]
+    pending : HashMap<usize, (StateKey, Decision)>,
+}
+
+impl InducedDecisionTree {
+    pub fn new(abstraction : StateAbstraction, max_depth : u32, epsilon : f64) -> Self {
+        InducedDecisionTree {
+            tree : None,
+            examples : vec![],
+            decision_history : vec![],
+            abstraction,
+            max_depth,
+            min_samples_split : 4,
+            max_examples : 5000,
+            epsilon,
+            pending : HashMap::new(),
+        }
+    }
+
+    pub fn fit(&mut self) {
+        let features = Feature::all();
+        let samples : Vec<Sample> = self.examples.iter()
+            .filter(|(_, _, reward)| *reward >= -1)
+            .map(|(key, decision, reward)| Sample {
+                values : features.iter().map(|f| f.value(key)).collect(),
+                decision : *decision,
+                weight : (*reward + 2) as f64,
+            })
+            .collect();
+        if samples.is_empty() {
+            return;
+        }
+        self.tree = Some(build(&features, samples.iter().collect(), 0, self.max_depth, self.min_samples_split as usize));
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        match &self.tree {
+            Some(tree) => tree.render(0, &mut out),
+            None => out.push_str("(no tree induced yet)\n"),
+        }
+        out
+    }
+}
+
+impl Policy for InducedDecisionTree {
+    fn make_a_decision(&mut self, tick : u128, a: &Animal, observation : Observation, rng: &mut oorandom::Rand32) -> Decision {
+        let agent = observation.agent;
+        let key = canonical_key(self.abstraction.summarize(a, observation));
+        let explore = (rng.rand_float() as f64) < self.epsilon;
+        let decision = match (&self.tree, explore) {
+            (Some(tree), false) => match tree.leaf(&key) {
+                TreeNode::Leaf { decision, samples } if *samples > 0 => *decision,
+                _ => DECISIONS[rng.rand_range(0..DECISIONS.len() as u32) as usize],
+            },
+            _ => DECISIONS[rng.rand_range(0..DECISIONS.len() as u32) as usize],
+        };
+        self.pending.insert(agent, (key.clone(), decision));
+        self.decision_history.push((tick, key, decision));
+        decision
+    }
+
+    fn learn(&mut self, outcome : &Outcome) {
+        if let Some((key, decision)) = self.pending.remove(&outcome.agent) {
+            self.examples.push((key, decision, outcome.hp_delta + outcome.ability_delta));
+        }
+        let overflow = self.examples.len().saturating_sub(self.max_examples as usize);
+        self.examples.drain(..overflow);
+    }
+
+    fn end_episode(&mut self) {
+        self.pending.clear();
+        self.fit();
+    }
+
+    // 复制出来的策略 (比如新生的孩子) 不能接着学原来那份还没学完的记录
+    fn clear_history(&mut self) {
+        self.decision_history = vec![];
+        self.pending.clear();
+    }
+
+    fn set_exploration(&mut self, exploration : f64) {
+        self.epsilon = exploration;
+    }
+
+    fn decision_history(&self) -> &[(u128, StateKey, Decision)] {
+        &self.decision_history
+    }
+
+    fn explain(&self) -> Option<String> {
+        Some(self.render())
+    }
+}
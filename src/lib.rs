@@ -0,0 +1,18 @@
+extern crate oorandom;
+extern crate serde;
+
+pub mod abstraction;
+pub mod budget;
+pub mod fields;
+pub mod genetic;
+pub mod imitation;
+pub mod induction;
+pub mod policy;
+pub mod qlearning;
+pub mod rendering;
+pub mod roads;
+pub mod simulation;
+pub mod training;
+pub mod utilities;
+pub mod world;
+pub mod zoning;
@@ -1,773 +1,469 @@
-extern crate oorandom;
-extern crate serde;
-
-use pixels::{Pixels, SurfaceTexture};
-use winit::{
-    dpi::LogicalSize,
-    // event::{Event, VirtualKeyCode},
-    event_loop::{ControlFlow, EventLoop},
-    window::{WindowBuilder, Window},
+use std::env;
+use std::process;
+
+use sim_city_build::abstraction::StateAbstraction;
+use sim_city_build::genetic::{genetic_run, GeneticConfig, Selection};
+use sim_city_build::imitation::{behavior_cloning, HumanTrace};
+use sim_city_build::induction::InducedDecisionTree;
+use sim_city_build::policy::{DecisionMakingTree, Policy, RewardMode, SamplingMode};
+use sim_city_build::qlearning::{QLearningPolicy, UpdateRule};
+use sim_city_build::world::WorldConfig;
+use sim_city_build::training::{
+    decision_making_evaluate, decision_making_replay, decision_making_run, decision_making_simulate,
+    decision_making_single_loop, resume_policy, TrainingConfig,
 };
-use std::{vec, collections::HashMap, str::FromStr};
-use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::Path;
-use serde_derive::{Serialize,Deserialize};
-use serde_with::serde_as;
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub enum Decision {
-    MoveUp,
-    MoveDown,
-    MoveLeft,
-    MoveRight,
-    Interact,
-    Build,
-    Wait,
+#[cfg(feature = "gui")]
+use sim_city_build::training::decision_making_play;
+
+const USAGE: &str = "\
+usage: sim-city-build <command> [options] [--world <json>] [--policy tree|qlearning|sarsa|induced]
+
+commands:
+    train       mutate, sample and reward a decision making tree
+                --runs <n> --mutate-factor <n> --reward-factor <n> --samples <n>
+                [--from <json>] [--to <json>] [--visual]
+                [--abstraction raw|summary] [--hp-bucket <n>] [--count-cap <n>]
+                [--explore-from <x> --explore-to <x>]
+                (the abstraction only applies to a policy trained from scratch;
+                the exploration, a temperature or epsilon, is annealed linearly over the runs)
+    evolve      evolve a population with selection, crossover, mutation and elitism
+                --population-size <n> --generations <n> --elitism <n> --mutate-factor <n>
+                [--selection tournament|roulette] [--tournament-size <n>]
+                [--population <json>] [--from <json>] [--to <json>]
+    evaluate    run a policy once without training, optionally saving its episode
+                [--from <json>] [--to <json>]
+    replay      re-execute the last episode recorded in a policy's decision history
+                --from <json> [--visual]
+    migrate     rewrite a policy saved in an older format with canonical state keys
+                --from <json> --to <json>
+                (legacy keys were made by an older Raw abstraction, so the migrated
+                weights are kept in the file but current states almost never match them)
+    explain     print a policy in readable form (induced trees only)
+                --from <json>
+    visualize   run a policy in the visualization window
+                [--from <json>]
+    simulate    run every spawned animal at once, each with its own copy of the policy
+                [--from <json>] [--to <json>] [--shared] [--tick-limit <n>] [--visual]
+                (--shared makes all animals decide and learn through one policy; with a
+                reproduction rule in the world config, children inherit a mutated copy;
+                --to saves the shared policy, or the copy of the animal that survived,
+                or lived longest if none did)
+    play        drive the animal yourself, one tick per keypress:
+                arrows move, I interacts, B builds, space waits,
+                A attacks, F flees, S shares with a neighbour,
+                Tab picks the next blueprint to build, E eats food, Esc quits
+                [--to <trace json>] [--abstraction raw|summary] [--hp-bucket <n>] [--count-cap <n>]
+                (sessions are appended to an existing trace, which keeps its own abstraction)
+    imitate     seed a decision making tree from recorded play sessions (behavior cloning)
+                --trace <json> [--trace <json> ...] --to <json> [--clone-factor <n>]
+
+every command accepts --world <json> to load a world generation config
+instead of the built-in default map (world_config.json); worlds/city.json
+pre-lays roads and turns on zoning, pollution/land value/crime fields and a
+city budget, with zoned buildings waiting for a power plant and a water tower.
+buildings that require a road only work next to a road network reaching the
+map edge, so a world with such buildings needs a road laid out to the edge.
+--policy picks the agent:
+    tree        the weight-bumping DecisionMakingTree (default)
+                [--reward-mode uniform|discounted] [--discount <x>] [--death-penalty <n>]
+                [--sampling proportional|softmax|epsilon-greedy|argmax] [--temperature <x>] [--epsilon <x>]
+                (proportional, the default, samples in proportion to the weights;
+                softmax samples in proportion to exp(weight / temperature))
+                (with --from, the loaded tree keeps its own reward mode and sampling
+                except for the flags given explicitly)
+    qlearning   tabular Q-learning, sarsa: tabular SARSA
+                [--learning-rate <x>] [--discount <x>] [--epsilon <x>]
+    induced     a decision tree induced from outcome-labelled history
+                [--max-depth <n>] [--epsilon <x>]";
+
+struct Options {
+    run_count : u32,
+    mutate_factor : u32,
+    reward_factor : u32,
+    sample_count : u32,
+    from_json : Option<String>,
+    to_json : Option<String>,
+    world_json : Option<String>,
+    abstraction : String,
+    hp_bucket : u32,
+    count_cap : u32,
+    policy : String,
+    learning_rate : f64,
+    discount : f64,
+    epsilon : f64,
+    max_depth : u32,
+    reward_mode : String,
+    death_penalty : u32,
+    sampling : String,
+    temperature : f64,
+    explore_from : Option<f64>,
+    explore_to : Option<f64>,
+    population_size : u32,
+    generations : u32,
+    elitism : u32,
+    selection : String,
+    tournament_size : u32,
+    population_json : Option<String>,
+    shared : bool,
+    tick_limit : Option<u128>,
+    trace_jsons : Vec<String>,
+    clone_factor : u32,
+    show_visuals : bool,
+    // 命令行上出现过的 flag, 用来区分明确给出的值和默认值
+    given : Vec<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct DecisionMaker {
-    pub decision_map : BTreeMap<Decision, u32>,
-}
-
-impl DecisionMaker {
-    pub fn default() -> Self {
-        use crate::Decision::*;
-        let mut map = BTreeMap::new();
-        map.insert(MoveUp, 2);
-        map.insert(MoveDown, 2);
-        map.insert(MoveLeft, 2);
-        map.insert(MoveRight, 2);
-        map.insert(Interact, 2);
-        map.insert(Build, 0);
-        map.insert(Wait, 10);
-        DecisionMaker{
-            decision_map : map
-        }
-    }
-    pub fn make_decision(&self, rng : &mut oorandom::Rand32) -> Decision {
-        let mut sum = 0;
-        for (_, v) in &self.decision_map {
-            sum += v;
-        }
-        let rand = rng.rand_u32() % sum;
-        sum = 0;
-        for (d, v) in &self.decision_map {
-            sum += v;
-            if sum >= rand {
-                // print!("sum :{:?}, rand:{:?}",sum, rand);
-                return d.clone();
-            }
-        }
-        Decision::Wait
-    }
-    pub fn decrease_chance(&mut self, d:Decision, i: u32) {
-        let de = (self.decision_map[&d] as i32 - i as i32) as i32;
-        let chance = if de>0 {de} else {0} as u32;
-        self.decision_map.insert(d.clone(), chance);
+impl Options {
+    fn default() -> Self {
+        Options {
+            run_count : 1,
+            mutate_factor : 1,
+            reward_factor : 1,
+            sample_count : 1,
+            from_json : None,
+            to_json : None,
+            world_json : None,
+            abstraction : String::from("summary"),
+            hp_bucket : 5,
+            count_cap : 3,
+            policy : String::from("tree"),
+            learning_rate : 0.1,
+            discount : 0.9,
+            epsilon : 0.1,
+            max_depth : 6,
+            reward_mode : String::from("uniform"),
+            death_penalty : 10,
+            sampling : String::from("proportional"),
+            temperature : 1.0,
+            explore_from : None,
+            explore_to : None,
+            population_size : 10,
+            generations : 10,
+            elitism : 2,
+            selection : String::from("tournament"),
+            tournament_size : 3,
+            population_json : None,
+            shared : false,
+            tick_limit : None,
+            trace_jsons : vec![],
+            clone_factor : 5,
+            show_visuals : false,
+            given : vec![],
+        }
+    }
+
+    fn given(&self, flag : &str) -> bool {
+        self.given.iter().any(|f| f == flag)
     }
-    pub fn increase_chance(&mut self, d: Decision, i : u32) {
-        let chance = self.decision_map[&d] + i;
-        self.decision_map.insert(d.clone(), chance);
-    }
-    pub fn mutate_chance(&mut self, mu : u32, rng : &mut oorandom::Rand32) {
-        let mut vdis = vec![];
-        for (dis, _) in &mut self.decision_map {
-            vdis.push(dis.clone());
-        }
-        for dis in vdis {
-            let rand = rng.rand_u32() % 2;
-            match rand {
-                0 => &self.increase_chance(dis, mu),
-                _ => &self.decrease_chance(dis, mu),
-            };
-        }
-    }
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
-pub enum Direction {
-    Up,
-    Dowm,
-    Right, 
-    Left,
-}
-
-
-#[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
-pub enum DecisionFactor {
-    DistanceDirection(u32, Direction, EnvironmentTag),
-    CurrentLocation(EnvironmentTag),
-    CurrentHp(i32),
 }
 
-#[serde_as]
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct DecisionMakingTree {
-    #[serde_as(as = "Vec<(_, _)>")]
-    decision_chain : HashMap<Vec<DecisionFactor>, DecisionMaker>,
-    // FIXME 历史记录
-    decision_history : Vec<(u128, Vec<DecisionFactor>, Decision)>,
+fn fail(msg : String) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    process::exit(2);
 }
 
-impl DecisionMakingTree {
-    pub fn from_json(_path_name: String) -> DecisionMakingTree {
-        let path = Path::new(_path_name.as_str());
-        let mut file = File::open(&path).unwrap();
-        let mut serialized : String= String::new(); 
-        file.read_to_string(&mut serialized).unwrap();
-        serde_json::from_str(serialized.as_str()).unwrap()
-    }
-
-    // pub fn init_json() {
-    //     let dmt = DecisionMakingTree{decision_chain:HashMap::new(),decision_history:vec![]};
-    //     let path = Path::new("decision_making_tree.json");
-    //     let mut file = File::create(&path).unwrap();
-    //     let serialized = serde_json::to_string(&dmt).unwrap();
-    //     file.write(serialized.as_bytes()).unwrap();
-    // }
-
-    pub fn to_json(&self, json_path:String) {
-        let serialized = serde_json::to_string(&self);
-        match serialized {
-            Ok(res) => {
-                let path = Path::new(json_path.as_str());
-                let mut file = File::create(&path).unwrap();
-                file.write(res.as_bytes()).unwrap();
-            },
-            Err(msg) => {
-                panic!("{:?}", msg);
-            }
-        }
-    }
-    // FIXME 实现
-    fn mutate_impl(mut self, _mutate_factor:u32, rng :&mut oorandom::Rand32) -> DecisionMakingTree {
-        for (_, dm) in &mut self.decision_chain {
-            dm.mutate_chance(_mutate_factor, rng);
-        }
-        self
-    }
-
-    fn reward_impl(mut self, _reward_factor:u32) -> DecisionMakingTree {
-        for (_, vdf, dis) in &self.decision_history {
-            self.decision_chain.get_mut(vdf).unwrap().increase_chance(dis.clone(), _reward_factor);
-        }
-        self
-    }
-    
-    pub fn reward(&self, _reward_factor:u32) -> DecisionMakingTree {
-        self.clone().reward_impl(_reward_factor)
-    }
-
-    pub fn mutate(&self, _mutate_factor:u32, rng :&mut oorandom::Rand32) -> DecisionMakingTree {
-        self.clone().mutate_impl(_mutate_factor, rng)
-    }
-
-    fn generate_default_decision_maker(&mut self, vdf : Vec<DecisionFactor>) -> DecisionMaker {
-        self.decision_chain.insert(vdf, DecisionMaker::default());
-        DecisionMaker::default()
-    }
-
-    fn get_decision_maker(&mut self, vdf : Vec<DecisionFactor>) -> DecisionMaker {
-        let decision_chain_get = self.decision_chain.get(&vdf);
-        let decision_maker = match decision_chain_get {
-            Some(d_maker) => d_maker.clone(),
-            None => self.generate_default_decision_maker(vdf),
-        };
-        decision_maker
-    }
-
-    fn calculate_decision_factors(&mut self, a: &Animal, ve : Vec<Environment>) -> Vec<DecisionFactor> {
-        let mut vdf = vec![];
-        for e in ve {
-            if e.position == a.position {
-                vdf.push(DecisionFactor::CurrentLocation(e.tag));
-                continue;
-            } 
-            let dis = distance(&e, a);
-            let dir = if i32::abs(e.position.0 - a.position.0) > i32::abs(e.position.1 - e.position.1) {
-                if e.position.0 > a.position.0 {
-                    Direction::Up
-                } else {
-                    Direction::Dowm
-                }
-            } else {
-                if e.position.1 > a.position.1 {
-                    Direction::Right
-                } else {
-                    Direction::Left
-                }
-            };
-            vdf.push(DecisionFactor::DistanceDirection(dis as u32, dir, e.tag));
-        }
-        vdf.push(DecisionFactor::CurrentHp(a.hp));
-        vdf
-    }
-
-    pub fn make_a_decision(&mut self, tick : u128, a: &Animal, ve : Vec<Environment>, rng: &mut oorandom::Rand32) -> Decision {
-        let vdf = self.calculate_decision_factors(a, ve);
-        let vdc = vdf.clone();
-        let decision = self.make_a_decision_impl(vdf, rng);
-        self.decision_history.push((tick, vdc, decision));
-        decision
-    }
-
-    fn make_a_decision_impl(&mut self, vdf : Vec<DecisionFactor>, rng:&mut oorandom::Rand32) -> Decision {
-        let decision_maker = self.get_decision_maker(vdf);
-        decision_maker.make_decision(rng)
+fn parse_number(flag : &str, value : Option<String>) -> u32 {
+    match value {
+        Some(v) => v.parse().unwrap_or_else(|_| fail(format!("{} expects a number, got {:?}", flag, v))),
+        None => fail(format!("{} expects a value", flag)),
     }
 }
 
-pub trait Tickable {
-    fn tick(&mut self);
-}
-
-// 做决定, 执行决定, 移动, 都超出了类Animal的可视范围.
-#[derive(Clone, Copy, Debug)]
-struct Animal {
-    pub alive : bool,
-    pub hp : i32,
-    pub ability : u32,
-    pub lifetime : u32,
-    pub position : (i32, i32),
-    view_distance: u32,
-    pub next_decision : Decision,
-}
-
-impl Animal {
-    pub fn spwan(a : &Animal, pos:(i32,i32)) ->Animal {
-        Animal { 
-            alive : true,
-            hp : a.hp,
-            ability : a.ability,
-            lifetime : 0,
-            position : pos,
-            view_distance: a.view_distance,
-            next_decision : a.next_decision,
-        }
-    }
-
-    fn consume(&mut self, num : i32) {
-        if !self.alive {
-            return
-        }
-        self.hp -= num;
-        if self.hp <= 0 {
-            self.alive = false;
-            return
-        }
-    }
-
-    pub fn move_inc(&mut self, inc:(i32, i32)) {
-        self.position.0 += inc.0;
-        self.position.1 += inc.1;
-        if self.position.0 < 0 {
-            self.position.0 = 0;
-        } 
-        if self.position.0 >= WIDTH as i32 {
-            self.position.0 = WIDTH as i32 - 1;
-        }
-        
-        if self.position.1 < 0 {
-            self.position.1 = 0;
-        } 
-        if self.position.1 >= HEIGHT as i32 {
-            self.position.1 = HEIGHT as i32 - 1;
-        }
-        
-    }
-
-    pub fn get_center_pixel_pos(&self) -> (i32, i32) {
-        (((GRID_WIDTH + 1) / 2 + GRID_WIDTH * self.position.0 as u32) as i32,
-        ((GRID_HEIGHT + 1) / 2 + GRID_HEIGHT * self.position.1 as u32) as i32)
+fn parse_float(flag : &str, value : Option<String>) -> f64 {
+    match value {
+        Some(v) => v.parse().unwrap_or_else(|_| fail(format!("{} expects a number, got {:?}", flag, v))),
+        None => fail(format!("{} expects a value", flag)),
     }
 }
 
-impl Tickable for Animal {
-    fn tick(&mut self) {
-        if !self.alive {
-            return;
-        }
-        self.lifetime += 1;
-        self.consume(1);
-        if !self.alive {
-            return;
-        }
+fn parse_path(flag : &str, value : Option<String>) -> String {
+    match value {
+        Some(v) => v,
+        None => fail(format!("{} expects a path", flag)),
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)] 
-pub enum EnvironmentTag {
-    DANGER,
-    CHALLENGE,
-    SHELTER,
-    DEFAULT,    
-}
-
-#[derive(Clone, Debug)]
-struct Environment {
-    tag : EnvironmentTag,
-    pub alive : bool,
-    pub auto_interact : bool,
-    pub hp: i32,
-    pub difficulty : u32,
-    pub penalty : u32,
-    pub reward : (u32, u32),
-    pub position: (i32, i32),
-    pub color : (u8, u8, u8, u8),
-    pub draw_type : DrawType,
-    pub d : u32,
+fn parse_options(args : impl Iterator<Item = String>) -> Options {
+    let mut args = args;
+    let mut options = Options::default();
+    while let Some(flag) = args.next() {
+        options.given.push(flag.clone());
+        match flag.as_str() {
+            "--runs" => options.run_count = parse_number(&flag, args.next()),
+            "--mutate-factor" => options.mutate_factor = parse_number(&flag, args.next()),
+            "--reward-factor" => options.reward_factor = parse_number(&flag, args.next()),
+            "--samples" => options.sample_count = parse_number(&flag, args.next()),
+            "--from" => options.from_json = Some(parse_path(&flag, args.next())),
+            "--to" => options.to_json = Some(parse_path(&flag, args.next())),
+            "--world" => options.world_json = Some(parse_path(&flag, args.next())),
+            "--abstraction" => options.abstraction = parse_path(&flag, args.next()),
+            "--hp-bucket" => options.hp_bucket = parse_number(&flag, args.next()),
+            "--count-cap" => options.count_cap = parse_number(&flag, args.next()),
+            "--policy" => options.policy = parse_path(&flag, args.next()),
+            "--learning-rate" => options.learning_rate = parse_float(&flag, args.next()),
+            "--discount" => options.discount = parse_float(&flag, args.next()),
+            "--epsilon" => options.epsilon = parse_float(&flag, args.next()),
+            "--max-depth" => options.max_depth = parse_number(&flag, args.next()),
+            "--population-size" => options.population_size = parse_number(&flag, args.next()),
+            "--generations" => options.generations = parse_number(&flag, args.next()),
+            "--elitism" => options.elitism = parse_number(&flag, args.next()),
+            "--selection" => options.selection = parse_path(&flag, args.next()),
+            "--tournament-size" => options.tournament_size = parse_number(&flag, args.next()),
+            "--population" => options.population_json = Some(parse_path(&flag, args.next())),
+            "--reward-mode" => options.reward_mode = parse_path(&flag, args.next()),
+            "--death-penalty" => options.death_penalty = parse_number(&flag, args.next()),
+            "--sampling" => options.sampling = parse_path(&flag, args.next()),
+            "--temperature" => options.temperature = parse_float(&flag, args.next()),
+            "--explore-from" => options.explore_from = Some(parse_float(&flag, args.next())),
+            "--explore-to" => options.explore_to = Some(parse_float(&flag, args.next())),
+            "--shared" => options.shared = true,
+            "--tick-limit" => options.tick_limit = Some(parse_number(&flag, args.next()) as u128),
+            "--trace" => options.trace_jsons.push(parse_path(&flag, args.next())),
+            "--clone-factor" => options.clone_factor = parse_number(&flag, args.next()),
+            "--visual" => options.show_visuals = true,
+            _ => fail(format!("unknown option {:?}", flag)),
+        }
+    }
+    options
 }
 
-#[derive(Clone, Debug)]
-pub enum DrawType {
-    Round,
-    Rect,
-    Line,
-    Pixel,
-    Circle,
-    Star,
-    None,
-}
-
-impl Environment {
-    pub fn spwan(e : &Environment, pos:(i32,i32)) ->Environment {
-        Environment { 
-            alive: true,
-            auto_interact : e.auto_interact,
-            tag: e.tag.clone(), 
-            hp: e.hp, 
-            difficulty: e.difficulty, 
-            penalty: e.penalty, 
-            reward: e.reward, 
-            position: pos,
-            color : e.color,
-            draw_type : e.draw_type.clone(),
-            d: e.d,
-        }
-    }
-
-    fn consume(&mut self, num : i32) {
-        if !self.alive {
-            return
-        }
-        self.hp -= num;
-        if self.hp <= 0 {
-            self.alive = false;
-            return
-        }
-    }
-
-    pub fn get_center_pixel_pos(&self) -> (i32, i32) {
-        (((GRID_WIDTH + 1) / 2 + GRID_WIDTH * self.position.0 as u32) as i32,
-        ((GRID_HEIGHT + 1) / 2 + GRID_HEIGHT * self.position.1 as u32) as i32)
+fn build_abstraction(options : &Options) -> StateAbstraction {
+    match options.abstraction.as_str() {
+        "raw" => StateAbstraction::Raw,
+        "summary" => StateAbstraction::Summary {
+            hp_bucket : options.hp_bucket,
+            count_cap : options.count_cap,
+        },
+        other => fail(format!("unknown abstraction {:?}, expected raw or summary", other)),
     }
 }
 
-fn distance(e : &Environment, a: &Animal) -> i32 {
-    i32::abs(e.position.0-a.position.0) + i32::abs(e.position.1-a.position.1)
-}
-
-// 只拿来做决策,不用来做更新,因此不需要引用
-fn find_environments(a:&Animal, ve:&Vec<Environment>) -> Vec<Environment> {
-    let mut vec = vec!();
-    for e in ve {
-        if distance(e, a) <= a.view_distance as i32 {
-            vec.push(e.clone());
-        }
+fn build_genetic_config(options : &Options) -> GeneticConfig {
+    let selection = match options.selection.as_str() {
+        "tournament" => Selection::Tournament(options.tournament_size.max(1)),
+        "roulette" => Selection::Roulette,
+        other => fail(format!("unknown selection {:?}, expected tournament or roulette", other)),
+    };
+    GeneticConfig {
+        population_size : options.population_size,
+        generations : options.generations,
+        elitism : options.elitism,
+        selection,
+        mutate_factor : options.mutate_factor,
     }
-    vec
 }
 
-const WIDTH: u32 = 50;
-const HEIGHT: u32 = 50;
-const WINDOW_WIDTH: u32 = 500;
-const WINDOW_HEIGHT: u32 = 500;
-const GRID_WIDTH: u32 = WINDOW_WIDTH/WIDTH;
-const GRID_HEIGHT: u32 = WINDOW_HEIGHT/HEIGHT;
-
-fn build_window() -> (EventLoop<()>, Window, Pixels) {
-    let event_loop = EventLoop::new();
-    // let input = WinitInputHelper::new();
-    let window = {
-        let size = 
-            LogicalSize::new(WINDOW_WIDTH as f64, WINDOW_HEIGHT as f64);
-        let scaled_size = 
-            LogicalSize::new(WINDOW_WIDTH as f64, WINDOW_HEIGHT as f64);
-        WindowBuilder::new()
-            .with_title("simulation visualization")
-            .with_inner_size(scaled_size)
-            .with_min_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
-    };
-
-    let pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = 
-            SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WINDOW_WIDTH, WINDOW_HEIGHT, surface_texture).unwrap()
+fn build_training_config(options : &Options) -> TrainingConfig {
+    let exploration = match (options.explore_from, options.explore_to) {
+        (Some(from), Some(to)) => Some((from, to)),
+        (None, None) => None,
+        _ => fail(String::from("--explore-from and --explore-to must be given together")),
     };
-    (event_loop, window, pixels)
-}
-
-fn visualize_map(ve : &Vec<Environment>, va : &Vec<Animal>, screen: &mut [u8]) {
-    for e in ve {
-        let pos = e.get_center_pixel_pos();
-        match e.draw_type {
-            DrawType::Round => draw_round(screen, pos.0, pos.1, e.d as i32, 
-                e.color.0,e.color.1,e.color.2,e.color.3),
-            DrawType::Pixel => draw_pixel(screen, pos.0, pos.1,
-                e.color.0,e.color.1,e.color.2,e.color.3),
-            DrawType::Rect => draw_rect(screen, pos.0, pos.1, e.d as i32, 
-                e.color.0,e.color.1,e.color.2,e.color.3),
-            _ => (),
-        }
-    }
-    for a in va {
-        let pos = a.get_center_pixel_pos();
-        draw_star(screen, pos.0, pos.1, 0xff,0xff,0,0xff);
+    TrainingConfig {
+        run_count : options.run_count,
+        mutate_factor : options.mutate_factor,
+        reward_factor : options.reward_factor,
+        sample_count : options.sample_count,
+        exploration,
     }
 }
 
-const STAR : [(i32, i32); 76]= [
-    (-6,0),(-6,-1),
-    (-5,0),(-5,-1),(-5,3),(-5,4),(-5,-4),(-5,-5),
-    (-4,0),(-4,2),(-4,3),(-4,4),(-4,-1),(-4,-3),(-4,-4),(-4,-5),
-    (-3,1),(-3,2),(-3,-2),(-3,-3),(-3,0),(-3,-1),(-3,3),(-3,-1),
-    (-2,1),(-2,2),(-2,-2),(-2,-3),
-    (-1,0),(-1,-1),(-1,-3),(-1,-4),(-1,-5),(-1,-6),(-1,2),(-1,3),(-1,4),(-1,5),
-    (0,0),(0,-1),(0,-3),(0,-4),(0,-5),(0,-6),(0,2),(0,3),(0,4),(0,5),
-    (1,1),(1,2),(1,-2),(1,-3),
-    (2,1),(2,2),(2,-2),(2,-3),(2,0),(2,-1),(2,3),(2,-1),
-    (3,0),(3,2),(3,3),(3,4),(3,-1),(3,-3),(3,-4),(3,-5),
-    (4,0),(4,-1),(4,3),(4,4),(4,-4),(4,-5),
-    (5,0),(5,-1),
-];
-
-fn draw_star(screen: &mut [u8], x:i32, y:i32, r:u8,g:u8,b:u8,a:u8) {
-    for (i, j) in STAR {
-        draw_pixel(screen, x + i + 1, y + j + 1, r, g, b, a);
-    }
+fn exit_on_error<T, E : std::fmt::Display>(result : Result<T, E>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
 }
 
-fn draw_rect(screen: &mut [u8], x:i32, y:i32, d:i32, 
-    r:u8,g:u8,b:u8,a:u8) {
-    let half_d = (d + 1) / 2;
-    for i in 0..half_d {
-        for j in 0..half_d {
-            draw_pixel(screen, x - i, y - j, r, g, b, a);
-            draw_pixel(screen, x - i, y + j, r, g, b, a);
-            draw_pixel(screen, x + i, y - j, r, g, b, a);
-            draw_pixel(screen, x + i, y + j, r, g, b, a);
-        }
+fn load_policy<P : Policy>(from_json : Option<String>, initial_policy : P) -> P {
+    match from_json {
+        Some(json_path) => exit_on_error(P::from_json(json_path)),
+        None => initial_policy,
     }
 }
 
-fn draw_round(screen: &mut [u8], x:i32, y:i32, d:i32, 
-    r:u8,g:u8,b:u8,a:u8) {
-    let half_d = (d + 1) / 2;
-    let sqr_half_d = half_d * half_d;
-
-    for i in 0..half_d {
-        for j in 0..half_d {
-            if (i*i + j*j) <= sqr_half_d {
-                draw_pixel(screen, x - i, y - j, r, g, b, a);
-                draw_pixel(screen, x - i, y + j, r, g, b, a);
-                draw_pixel(screen, x + i, y - j, r, g, b, a);
-                draw_pixel(screen, x + i, y + j, r, g, b, a);
-            }
-        }
+fn load_world(world_json : Option<String>) -> WorldConfig {
+    match world_json {
+        Some(json_path) => exit_on_error(WorldConfig::from_json(json_path)),
+        None => WorldConfig::default(),
     }
 }
 
-fn draw_pixel(screen: &mut [u8], x:i32, y:i32, r:u8,g:u8,b:u8,a:u8) {
-    if x<0 || y<0 || x * WINDOW_HEIGHT as i32 * 4 + y * 4 + 3 >= screen.len() as i32 {
-        return;
-    }
-    let x = x as usize;
-    let y = y as usize;
-    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4] = r;
-    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4 + 1] = g;
-    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4 + 2] = b;
-    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4 + 3] = a;
+fn imitate(options : Options) {
+    if options.trace_jsons.is_empty() {
+        fail(String::from("imitate requires at least one --trace"));
+    }
+    let json_path = options.to_json.unwrap_or_else(|| fail(String::from("imitate requires --to")));
+    let traces : Vec<HumanTrace> = options.trace_jsons.into_iter()
+        .map(|trace_json| exit_on_error(HumanTrace::from_json(trace_json)))
+        .collect();
+    let skipped = traces.iter().filter(|t| t.abstraction != traces[0].abstraction).count();
+    if skipped > 0 {
+        println!("skipping {} traces recorded with a different abstraction than the first", skipped);
+    }
+    let policy = behavior_cloning(&traces, options.clone_factor);
+    println!("CLONED {} STATES", policy.decision_chain.len());
+    exit_on_error(policy.to_json(json_path));
 }
 
-fn make_interaction(e : &mut Environment, a : &mut Animal, rng: &mut oorandom::Rand32) {
-    let dif = e.difficulty;
-    let roll = rng.rand_u32() % 20;
-    let dix = roll + a.ability;
-    e.consume(1);
-    if dix >= dif {
-        a.hp += e.reward.0 as i32;
-        a.ability += e.reward.1;
-    } else {
-        a.consume(e.penalty as i32);
-    }
-
-}
-
-fn execute_decision(ve: &mut Vec<Environment>, a :&mut Animal, rng :&mut oorandom::Rand32) {
-    let mut vme : Vec<&mut Environment> = vec![];
-    for e in ve.iter_mut() {
-        if e.position == a.position {
-            vme.push(e);
-        }
-    }
-    for me in vme.iter_mut() {
-        if me.auto_interact {
-            make_interaction(me, a, rng);
-        }
-    }
-    match a.next_decision {
-        Decision::MoveUp => {
-            a.move_inc((1, 0));
-        },
-        Decision::MoveDown => {
-            a.move_inc((-1, 0));
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = match args.next() {
+        Some(command) => command,
+        None => fail(String::from("missing command")),
+    };
+    let options = parse_options(args);
+    if cfg!(not(feature = "gui")) && (options.show_visuals || command == "visualize" || command == "play") {
+        fail(String::from("this binary was built without the `gui` feature; visuals are unavailable"));
+    }
+    let world_config = load_world(options.world_json.clone());
+    let abstraction = build_abstraction(&options);
+    match command.as_str() {
+        "play" => {
+            #[cfg(feature = "gui")]
+            exit_on_error(decision_making_play(&world_config, abstraction, options.to_json));
+            return;
         },
-        Decision::MoveLeft => {
-            a.move_inc((0, -1));
+        "imitate" => return imitate(options),
+        _ => (),
+    }
+    match options.policy.as_str() {
+        "tree" => {
+            let initial_policy = DecisionMakingTree::with_abstraction(abstraction);
+            run_command(&command, options, &world_config, initial_policy, configure_tree)
         },
-        Decision::MoveRight => {
-            a.move_inc((0, 1));
+        "qlearning" | "sarsa" => {
+            let update_rule = if options.policy == "sarsa" { UpdateRule::Sarsa } else { UpdateRule::QLearning };
+            let initial_policy = QLearningPolicy::new(
+                abstraction, update_rule, options.learning_rate, options.discount, options.epsilon);
+            run_command(&command, options, &world_config, initial_policy, |_, _| ())
         },
-        Decision::Interact => {
-            for me in vme.iter_mut() {
-                if !me.auto_interact {
-                    make_interaction(me, a, rng);
-                }
-            }
+        "induced" => {
+            let initial_policy = InducedDecisionTree::new(abstraction, options.max_depth, options.epsilon);
+            run_command(&command, options, &world_config, initial_policy, |_, _| ())
         },
-        Decision::Build => {
-            a.consume(5);
-            ve.push(Environment{
-                alive: true,
-                tag: EnvironmentTag::SHELTER, 
-                auto_interact : true,
-                hp: 5, 
-                difficulty: 0, 
-                penalty: 0, 
-                reward: (1,0),
-                position: (0,0),
-                draw_type: DrawType::Rect,
-                color: (0, 0xff, 0, 0x7f),
-                d:GRID_WIDTH,
-            });
-        },
-        // Decision::Wait => {},
-        _ => {},
+        other => fail(format!("unknown policy {:?}, expected tree, qlearning, sarsa or induced", other)),
     }
 }
 
-fn garbage_collection(ve: Vec<Environment>) -> Vec<Environment> {
-    let mut ret = vec![];
-    for e in ve {
-        if e.alive {
-            ret.push(e);
-        }
+// 读到的决策树保留自己的 reward mode 和 sampling, 命令行上明确给出的 flag 才覆盖对应的部分;
+// 从头开始的决策树也走这里, 没给的 flag 用 Options 的默认值.
+fn configure_tree(options : &Options, policy : &mut DecisionMakingTree) {
+    if options.given("--reward-mode") || options.given("--discount") || options.given("--death-penalty") {
+        let (discount, death_penalty) = match policy.reward_mode {
+            RewardMode::Discounted { discount, death_penalty } => (discount, death_penalty),
+            RewardMode::Uniform => (options.discount, options.death_penalty as i32),
+        };
+        let mode = match (options.given("--reward-mode"), &policy.reward_mode) {
+            (true, _) => options.reward_mode.as_str(),
+            (false, RewardMode::Uniform) => "uniform",
+            (false, RewardMode::Discounted { .. }) => "discounted",
+        };
+        policy.reward_mode = match mode {
+            "uniform" => RewardMode::Uniform,
+            "discounted" => RewardMode::Discounted {
+                discount : if options.given("--discount") { options.discount } else { discount },
+                death_penalty : if options.given("--death-penalty") { options.death_penalty as i32 } else { death_penalty },
+            },
+            other => fail(format!("unknown reward mode {:?}, expected uniform or discounted", other)),
+        };
     }
-    ret
-}
-
-fn generate_map() -> (Vec<Environment>,Vec<Animal>) {
-    let initializer_seed = 64;
-    let mut rng_initializer = oorandom::Rand32::new(initializer_seed);
-    let shelter_chance = 10;
-    let shelter = Environment{
-        alive:true,
-        tag: EnvironmentTag::SHELTER, 
-        auto_interact : true,
-        hp: 5, 
-        difficulty: 0, 
-        penalty: 0, 
-        reward: (1,0),
-        position: (0,0),
-        draw_type: DrawType::Rect,
-        color: (0, 0xff, 0, 0x7f),
-        d:GRID_WIDTH,
-    };
-    let challenge_chance = 2;
-    let challenge = Environment{
-        alive:true,
-        tag: EnvironmentTag::CHALLENGE, 
-        auto_interact : false,
-        hp: 1, 
-        difficulty: 10, 
-        penalty: 2, 
-        reward: (5,0),
-        position: (0,0),
-        draw_type: DrawType::Round,
-        color: (0, 0xff, 0xff, 0xaf),
-        d:GRID_WIDTH-1,
-    };
-    let danger_chance = 2;
-    let danger = Environment{
-        alive:true,
-        tag: EnvironmentTag::DANGER, 
-        auto_interact : false,
-        hp: i32::MAX, 
-        difficulty: 10, 
-        penalty: 2, 
-        reward: (0,0),
-        position: (0,0),
-        draw_type: DrawType::Round,
-        color: (0xff, 0, 0, 0xaf),
-        d:GRID_WIDTH-2,
-    };
-    // 先不去考虑obstacle
-    // let obstacle = Environment{
-    //     tag: String::from("obstacle"),
-    //     auto_interact : false,
-    //     hp: MAX, 
-    //     difficulty: MAX, 
-    //     penalty: 1, 
-    //     reward: (0,0),
-    //     position: (0,0)
-    // };
-    let mut ve = vec![];
-    for i in 1..WIDTH {
-        for j in 1..HEIGHT {
-            let rng = rng_initializer.rand_u32() % 20;
-            if rng >= shelter_chance {
-                ve.push(Environment::spwan(&shelter, (i as i32, j as i32)));
-            } else if rng >= shelter_chance - challenge_chance {
-                ve.push(Environment::spwan(&challenge, (i as i32, j as i32)));
-            } else if rng >= shelter_chance - challenge_chance - danger_chance {
-                ve.push(Environment::spwan(&danger, (i as i32, j as i32)));
-            }
-        }
+    if options.given("--sampling") || options.given("--temperature") || options.given("--epsilon") {
+        let (temperature, epsilon) = match policy.sampling {
+            SamplingMode::Softmax { temperature } => (temperature, options.epsilon),
+            SamplingMode::EpsilonGreedy { epsilon } => (options.temperature, epsilon),
+            SamplingMode::Proportional | SamplingMode::Argmax => (options.temperature, options.epsilon),
+        };
+        let sampling = match (options.given("--sampling"), &policy.sampling) {
+            (true, _) => options.sampling.as_str(),
+            (false, SamplingMode::Proportional) => "proportional",
+            (false, SamplingMode::Softmax { .. }) => "softmax",
+            (false, SamplingMode::EpsilonGreedy { .. }) => "epsilon-greedy",
+            (false, SamplingMode::Argmax) => "argmax",
+        };
+        policy.sampling = match sampling {
+            "proportional" => SamplingMode::Proportional,
+            "softmax" => SamplingMode::Softmax {
+                temperature : if options.given("--temperature") { options.temperature } else { temperature },
+            },
+            "epsilon-greedy" => SamplingMode::EpsilonGreedy {
+                epsilon : if options.given("--epsilon") { options.epsilon } else { epsilon },
+            },
+            "argmax" => SamplingMode::Argmax,
+            other => fail(format!("unknown sampling {:?}, expected proportional, softmax, epsilon-greedy or argmax", other)),
+        };
     }
-    let normal = Animal {
-        alive : true,
-        hp : 10,
-        ability : 5,
-        lifetime : 0,
-        position : (0, 0),
-        view_distance: 5,
-        next_decision : Decision::Wait,
-    };
-    let va = vec![
-        Animal::spwan(&normal, (25,25))
-    ];
-    (ve, va)
 }
 
-fn decision_making_single_loop(
-    _show_visuals: bool, 
-    mut _decision_making_tree: DecisionMakingTree,
-) -> (DecisionMakingTree, u128) {
-    let mut tick : u128 = 0;
-    let calculator_seed = 64;
-    let mut rng_calculator = oorandom::Rand32::new(calculator_seed);
-    let (mut ve,mut va) = generate_map();
-    if _show_visuals {
-        let (event_loop, window, mut pixels) = build_window();
-        event_loop.run(move |_, _, control_flow| {
-            if !va[0].alive {
-                println!("Player Dead in tick {:?}", tick);
-                *control_flow = ControlFlow::Exit;
-                return;
-            } else {
-                // 剩下的loop操作也在这里写.
-                clear_pixels(pixels.get_frame_mut());
-                visualize_map(&ve, &va, pixels.get_frame_mut());
-                pixels.render().unwrap();
-                let vde = find_environments(&va[0], &ve);
-                va[0].next_decision = _decision_making_tree.make_a_decision(tick, &va[0], vde, &mut rng_calculator);
-                execute_decision(&mut ve, &mut va[0], &mut rng_calculator);
-                for a in va.iter_mut() {
-                    a.tick();
-                }
-                tick += 1;
-                ve = garbage_collection(ve.clone());
-                window.request_redraw();
+fn run_command<P : Policy + 'static>(
+    command : &str,
+    options : Options,
+    world_config : &WorldConfig,
+    initial_policy : P,
+    configure : fn(&Options, &mut P),
+) {
+    // 只有 train 在读档失败时从空策略继续, 其它命令直接报错退出
+    let mut policy = match command {
+        "train" => resume_policy(options.from_json.clone(), initial_policy),
+        _ => load_policy(options.from_json.clone(), initial_policy),
+    };
+    configure(&options, &mut policy);
+    match command {
+        "train" => exit_on_error(decision_making_run(
+            options.show_visuals,
+            build_training_config(&options),
+            world_config,
+            policy,
+            options.to_json,
+        )),
+        "evolve" => {
+            exit_on_error(genetic_run(
+                build_genetic_config(&options),
+                world_config,
+                policy,
+                options.population_json,
+                options.to_json,
+            ));
+        },
+        "evaluate" => {
+            exit_on_error(decision_making_evaluate(world_config, policy, options.to_json));
+        },
+        "simulate" => {
+            exit_on_error(decision_making_simulate(
+                options.show_visuals,
+                world_config,
+                policy,
+                options.shared,
+                options.tick_limit,
+                options.to_json,
+            ));
+        },
+        "replay" => {
+            if options.from_json.is_none() {
+                fail(String::from("replay requires --from"));
             }
-        });
-    } else {
-        while va[0].alive {
-            let vde = find_environments(&va[0], &ve);
-            va[0].next_decision = _decision_making_tree.make_a_decision(tick, &va[0], vde, &mut rng_calculator);
-            execute_decision(&mut ve, &mut va[0], &mut rng_calculator);
-            for a in va.iter_mut() {
-                a.tick();
+            decision_making_replay(options.show_visuals, &policy, world_config);
+        },
+        "migrate" => {
+            if options.from_json.is_none() {
+                fail(String::from("migrate requires --from"));
             }
-            tick += 1;
-            ve = garbage_collection(ve.clone());
-            if !va[0].alive {
-                println!("Player Dead in tick {:?}", tick);
+            let json_path = options.to_json.unwrap_or_else(|| fail(String::from("migrate requires --to")));
+            exit_on_error(policy.to_json(json_path));
+        },
+        "explain" => {
+            if options.from_json.is_none() {
+                fail(String::from("explain requires --from"));
             }
-        }
-    }    
-    (_decision_making_tree, tick)
-}
-
-fn decision_making_run(
-    _show_visuals: bool, 
-    _run_count:u32,
-    _mutate_factor:u32,
-    _reward_factor:u32,
-    _sample_count:u32,
-    _from_json:Option<String>, 
-    _to_json:Option<String>) {
-    let mutator_seed = 64;
-    let mut rng_mutator = oorandom::Rand32::new(mutator_seed);
-    let mut decision_making_tree = match _from_json {
-        Some(json_path) => DecisionMakingTree::from_json(json_path),
-        None => DecisionMakingTree{
-            decision_history:vec!(), 
-            decision_chain:HashMap::new()
-        }
-    };
-    for run in 0.._run_count {
-        println!("RUNNING COUNT {:?}", run);
-        let mut result_vec = vec![];
-        for sample in 0.._sample_count {
-            println!("SAMPLE COUNT {:?}", sample);
-            let decision_making_sample = decision_making_tree.clone().mutate(_mutate_factor, &mut rng_mutator);
-            result_vec.push(decision_making_single_loop(_show_visuals, decision_making_sample));
-        }
-        let (mut rdmt, mut max_tick) = (
-            DecisionMakingTree{
-                decision_history:vec!(), 
-                decision_chain:HashMap::new()
-        }, 0);
-        for (dmt, tick) in result_vec {
-            if tick > max_tick {
-                rdmt = dmt;
-                max_tick = tick;
+            match policy.explain() {
+                Some(text) => print!("{}", text),
+                None => fail(String::from("this policy has no readable form")),
             }
-        }
-        decision_making_tree = rdmt.reward(_reward_factor);
-    }
-    match _to_json {
-        Some(json_path) => decision_making_tree.to_json(json_path),
-        None => (),
+        },
+        "visualize" => {
+            decision_making_single_loop(true, policy, world_config);
+        },
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        _ => fail(format!("unknown command {:?}", command)),
     }
 }
-
-fn main() {
-    decision_making_run(
-        true,
-        1,
-        1,
-        1,
-        1,
-        Some(String::from_str("decision_making_trainning_result_0.json").unwrap()),
-        // None,
-        Some(String::from_str("decision_making_trainning_result_1.json").unwrap()),
-    );
-}
-
-fn clear_pixels(pixels : &mut [u8]) {
-    for i in 0..WINDOW_WIDTH as usize {
-        for j in 0..WINDOW_HEIGHT as usize {
-            pixels[4 * (i * WINDOW_HEIGHT as usize + j)] = 0;
-            pixels[4 * (i * WINDOW_HEIGHT as usize + j) + 1] = 0;
-            pixels[4 * (i * WINDOW_HEIGHT as usize + j) + 2] = 0;
-            pixels[4 * (i * WINDOW_HEIGHT as usize + j) + 3] = 0;            
-        }
-    }
-}
\ No newline at end of file
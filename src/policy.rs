@@ -0,0 +1,813 @@
+use std::{vec, collections::HashMap};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde_derive::{Serialize,Deserialize};
+use serde_json::error::Category;
+use serde_with::serde_as;
+
+use crate::abstraction::StateAbstraction;
+use crate::fields::{Field, FieldLevels};
+use crate::simulation::{Animal, Environment, EnvironmentTag, Resource};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Decision {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    Build,
+    Wait,
+    // 对相邻的动物: 攻击, 逃离, 分 hp
+    Attack,
+    Flee,
+    Share,
+    // 换到下一个蓝图, Build 用
+    CycleBlueprint,
+    // 吃掉一份食物回复 hp
+    Eat,
+}
+
+pub const DECISIONS : [Decision; 12] = [
+    Decision::MoveUp,
+    Decision::MoveDown,
+    Decision::MoveLeft,
+    Decision::MoveRight,
+    Decision::Interact,
+    Decision::Build,
+    Decision::Wait,
+    Decision::Attack,
+    Decision::Flee,
+    Decision::Share,
+    Decision::CycleBlueprint,
+    Decision::Eat,
+];
+
+// DecisionMaker 怎么从权重里抽一个决定, 训练时可以从探索慢慢调到利用.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SamplingMode {
+    // 概率正比于权重, 权重为 0 的决定不会被选
+    Proportional,
+    // 概率正比于 exp(weight / temperature): 越大越接近均匀, 越小越接近 argmax; 权重为 0 的决定也会被探索
+    Softmax {
+        temperature : f64,
+    },
+    // epsilon 的概率均匀随机, 其余取权重最大的
+    EpsilonGreedy {
+        epsilon : f64,
+    },
+    // 总是取权重最大的, 一样大的随机选一个
+    Argmax,
+}
+
+impl SamplingMode {
+    pub fn proportional() -> Self {
+        SamplingMode::Proportional
+    }
+
+    // 当前模式下的探索程度: softmax 的 temperature, epsilon-greedy 的 epsilon.
+    pub fn with_exploration(self, exploration : f64) -> Self {
+        match self {
+            SamplingMode::Softmax { .. } => SamplingMode::Softmax { temperature : exploration },
+            SamplingMode::EpsilonGreedy { .. } => SamplingMode::EpsilonGreedy { epsilon : exploration },
+            SamplingMode::Proportional => SamplingMode::Proportional,
+            SamplingMode::Argmax => SamplingMode::Argmax,
+        }
+    }
+}
+
+// 权重是非负的 f64, 不要求和为 1; 抽样前由 probabilities 归一化.
+// 旧文件里的整数权重可以直接读成 f64.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecisionMaker {
+    pub decision_map : BTreeMap<Decision, f64>,
+}
+
+impl Default for DecisionMaker {
+    fn default() -> Self {
+        use crate::policy::Decision::*;
+        let mut map = BTreeMap::new();
+        map.insert(MoveUp, 2.0);
+        map.insert(MoveDown, 2.0);
+        map.insert(MoveLeft, 2.0);
+        map.insert(MoveRight, 2.0);
+        map.insert(Interact, 2.0);
+        map.insert(Build, 0.0);
+        map.insert(Wait, 10.0);
+        map.insert(Attack, 0.0);
+        map.insert(Flee, 0.0);
+        map.insert(Share, 0.0);
+        map.insert(CycleBlueprint, 0.0);
+        map.insert(Eat, 0.0);
+        DecisionMaker{
+            decision_map : map
+        }
+    }
+}
+
+impl DecisionMaker {
+    // 每个决定的权重都是 1.
+    pub fn uniform() -> Self {
+        DecisionMaker{
+            decision_map : DECISIONS.iter().map(|d| (*d, 1.0)).collect()
+        }
+    }
+
+    fn weight(w : f64) -> f64 {
+        if w.is_finite() && w > 0.0 { w } else { 0.0 }
+    }
+
+    // 按权重比例的概率, 和总是 1: 负数和 NaN 按 0 算, 全部为 0 时均匀分布.
+    pub fn proportional_probabilities(&self) -> BTreeMap<Decision, f64> {
+        let sum : f64 = self.decision_map.values().cloned().map(DecisionMaker::weight).sum();
+        if sum == 0.0 {
+            let p = 1.0 / self.decision_map.len() as f64;
+            return self.decision_map.keys().map(|d| (*d, p)).collect();
+        }
+        self.decision_map.iter().map(|(d, w)| (*d, DecisionMaker::weight(*w) / sum)).collect()
+    }
+
+    // softmax 的概率, 和总是 1; temperature <= 0 或 NaN 时退化成 argmax.
+    pub fn probabilities(&self, temperature : f64) -> BTreeMap<Decision, f64> {
+        let max = self.decision_map.values().cloned().map(DecisionMaker::weight).fold(0.0, f64::max);
+        if temperature.is_nan() || temperature <= 0.0 {
+            let best : Vec<Decision> = self.best_decisions();
+            let p = 1.0 / best.len() as f64;
+            return self.decision_map.keys()
+                .map(|d| (*d, if best.contains(d) { p } else { 0.0 }))
+                .collect();
+        }
+        // 减去最大的权重再取 exp 不会溢出, 最大的一项总是 1.
+        let scaled : BTreeMap<Decision, f64> = self.decision_map.iter()
+            .map(|(d, w)| (*d, ((DecisionMaker::weight(*w) - max) / temperature).exp()))
+            .collect();
+        let sum : f64 = scaled.values().sum();
+        scaled.into_iter().map(|(d, v)| (d, v / sum)).collect()
+    }
+
+    fn best_decisions(&self) -> Vec<Decision> {
+        let max = self.decision_map.values().cloned().map(DecisionMaker::weight).fold(0.0, f64::max);
+        self.decision_map.iter()
+            .filter(|(_, w)| DecisionMaker::weight(**w) == max)
+            .map(|(d, _)| *d)
+            .collect()
+    }
+
+    fn sample(probabilities : &BTreeMap<Decision, f64>, rng : &mut oorandom::Rand32) -> Decision {
+        let rand = rng.rand_float() as f64;
+        let mut sum = 0.0;
+        let mut last = Decision::Wait;
+        for (d, p) in probabilities {
+            if *p <= 0.0 {
+                continue;
+            }
+            sum += p;
+            last = *d;
+            if rand < sum {
+                return *d;
+            }
+        }
+        // 浮点误差让 sum 略小于 1 时落到最后一个非零项.
+        last
+    }
+
+    pub fn make_decision(&self, mode : &SamplingMode, rng : &mut oorandom::Rand32) -> Decision {
+        if self.decision_map.is_empty() {
+            return Decision::Wait;
+        }
+        match *mode {
+            SamplingMode::Proportional => DecisionMaker::sample(&self.proportional_probabilities(), rng),
+            SamplingMode::Softmax { temperature } => {
+                DecisionMaker::sample(&self.probabilities(temperature), rng)
+            },
+            SamplingMode::EpsilonGreedy { epsilon } => {
+                if (rng.rand_float() as f64) < epsilon {
+                    let i = rng.rand_range(0..self.decision_map.len() as u32) as usize;
+                    *self.decision_map.keys().nth(i).unwrap()
+                } else {
+                    DecisionMaker::sample(&self.probabilities(0.0), rng)
+                }
+            },
+            SamplingMode::Argmax => DecisionMaker::sample(&self.probabilities(0.0), rng),
+        }
+    }
+    // 逐个决定取权重的平均值.
+    pub fn average(vdm : &[DecisionMaker]) -> Self {
+        let mut map = BTreeMap::new();
+        for dm in vdm {
+            for (d, v) in &dm.decision_map {
+                *map.entry(*d).or_insert(0.0) += v;
+            }
+        }
+        for v in map.values_mut() {
+            *v /= vdm.len() as f64;
+        }
+        DecisionMaker{
+            decision_map : map
+        }
+    }
+    pub fn decrease_chance(&mut self, d:Decision, i: u32) {
+        let chance = (self.decision_map[&d] - i as f64).max(0.0);
+        self.decision_map.insert(d, chance);
+    }
+    pub fn increase_chance(&mut self, d: Decision, i : u32) {
+        let chance = self.decision_map[&d] + i as f64;
+        self.decision_map.insert(d, chance);
+    }
+    pub fn mutate_chance(&mut self, mu : u32, rng : &mut oorandom::Rand32) {
+        let mut vdis = vec![];
+        for dis in self.decision_map.keys() {
+            vdis.push(*dis);
+        }
+        for dis in vdis {
+            let rand = rng.rand_u32() % 2;
+            match rand {
+                0 => &self.increase_chance(dis, mu),
+                _ => &self.decrease_chance(dis, mu),
+            };
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    Up,
+    Dowm,
+    Right,
+    Left,
+}
+
+
+#[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DecisionFactor {
+    DistanceDirection(u32, Direction, EnvironmentTag),
+    CurrentLocation(EnvironmentTag),
+    CurrentHp(i32),
+    // 视野内某种环境的数量, 超过 count_cap 的按 count_cap 算
+    TagCount(EnvironmentTag, u32),
+    // hp / hp_bucket
+    HpBucket(i32),
+    // 视野内的其它动物: 距离, 方向, 是否同一物种
+    AnimalDirection(u32, Direction, bool),
+    // 同一格里有其它动物, 是否同一物种
+    AnimalHere(bool),
+    // 视野内同一物种 / 别的物种的动物数量, 超过 count_cap 的按 count_cap 算
+    AnimalCount(bool, u32),
+    // 这个方向上相邻的格子被障碍物挡住了
+    Blocked(Direction),
+    // 选中的蓝图; 选的是第一个蓝图时不出现, 这样没有换过蓝图的状态 key 不变
+    Blueprint(u32),
+    // 背包里某种资源的数量, Summary 时超过 count_cap 的按 count_cap 算; 没有的资源不出现
+    Stock(Resource, u32),
+    // 脚下格子某种场的档位 (值 / level_width); 档位为 0 的场不出现
+    FieldLevel(Field, u32),
+    // 国库余额的档位 (余额 / level_width); 世界配置里没有 budget 时不出现
+    Treasury(i32),
+}
+
+// 读写策略 JSON 时的错误, 让训练可以在读档失败时汇报并继续而不是直接 panic.
+#[derive(Debug)]
+pub enum PolicyError {
+    MissingFile(String),
+    Io(String, io::Error),
+    MalformedJson(String, serde_json::Error),
+    // 文件是合法的 JSON, 但不是期望的类型 (第二项是类型名)
+    IncompatibleSchema(String, String, serde_json::Error),
+    Serialize(String, serde_json::Error),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PolicyError::*;
+        match self {
+            MissingFile(path) => write!(f, "policy file {} does not exist", path),
+            Io(path, err) => write!(f, "cannot access policy file {}: {}", path, err),
+            MalformedJson(path, err) => write!(f, "policy file {} is not valid JSON: {}", path, err),
+            IncompatibleSchema(path, expected, err) => write!(f,
+                "policy file {} does not match the {} schema: {}", path, expected, err),
+            Serialize(path, err) => write!(f, "cannot serialize policy for {}: {}", path, err),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use PolicyError::*;
+        match self {
+            MissingFile(_) => None,
+            Io(_, err) => Some(err),
+            MalformedJson(_, err) | IncompatibleSchema(_, _, err) | Serialize(_, err) => Some(err),
+        }
+    }
+}
+
+fn read_policy_file(_path_name: &str) -> Result<String, PolicyError> {
+    let path = Path::new(_path_name);
+    let mut file = File::open(path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => PolicyError::MissingFile(_path_name.to_string()),
+        _ => PolicyError::Io(_path_name.to_string(), err),
+    })?;
+    let mut serialized : String= String::new();
+    file.read_to_string(&mut serialized)
+        .map_err(|err| PolicyError::Io(_path_name.to_string(), err))?;
+    Ok(serialized)
+}
+
+// 去掉模块路径的类型名, 例如 Vec<DecisionMakingTree>.
+fn short_type_name<P>() -> String {
+    let mut name = String::new();
+    let mut path = String::new();
+    for c in std::any::type_name::<P>().chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+        } else {
+            name.push_str(path.rsplit("::").next().unwrap_or(""));
+            path.clear();
+            name.push(c);
+        }
+    }
+    name.push_str(path.rsplit("::").next().unwrap_or(""));
+    name
+}
+
+pub fn policy_from_json<P: DeserializeOwned>(_path_name: String) -> Result<P, PolicyError> {
+    let serialized = read_policy_file(&_path_name)?;
+    serde_json::from_str(serialized.as_str()).map_err(|err| match err.classify() {
+        Category::Data => PolicyError::IncompatibleSchema(_path_name, short_type_name::<P>(), err),
+        _ => PolicyError::MalformedJson(_path_name, err),
+    })
+}
+
+pub fn policy_to_json<P: serde::Serialize>(policy: &P, json_path:String) -> Result<(), PolicyError> {
+    let serialized = serde_json::to_string(policy)
+        .map_err(|err| PolicyError::Serialize(json_path.clone(), err))?;
+    let path = Path::new(json_path.as_str());
+    File::create(path)
+        .and_then(|mut file| file.write_all(serialized.as_bytes()))
+        .map_err(|err| PolicyError::Io(json_path, err))
+}
+
+// 一只动物做决定时看到的世界: 视野内的环境和其它动物, 脚下格子的场和国库的档位.
+// 场和国库属于整个世界, 不复制到每只 Animal 上.
+#[derive(Clone, Debug, Default)]
+pub struct Observation {
+    // 做决定的动物在 va 里的下标; 共用策略时靠它把结果记到对应的动物上
+    pub agent : usize,
+    pub environments : Vec<Environment>,
+    pub animals : Vec<Animal>,
+    pub fields : FieldLevels,
+    // 世界配置里没有 budget 时为 None
+    pub treasury : Option<i32>,
+}
+
+// 一个 tick 执行完决定之后, 做决定的 Animal 的变化.
+#[derive(Clone, Copy, Debug)]
+pub struct Outcome {
+    pub tick : u128,
+    // 与做决定时 Observation 里的 agent 相同
+    pub agent : usize,
+    pub hp_delta : i32,
+    pub ability_delta : i32,
+    pub alive : bool,
+}
+
+impl Outcome {
+    pub fn between(tick : u128, agent : usize, before : &Animal, after : &Animal) -> Self {
+        Outcome {
+            tick,
+            agent,
+            hp_delta : after.hp - before.hp,
+            ability_delta : after.ability as i32 - before.ability as i32,
+            alive : after.alive,
+        }
+    }
+}
+
+// 模拟循环和训练只通过这个 trait 使用策略, 不同的 agent 可以放在同一个循环里比较.
+// 不做进化的策略可以保留 mutate / reward 的默认实现.
+pub trait Policy: Clone + serde::Serialize + DeserializeOwned {
+    fn make_a_decision(&mut self, tick : u128, a: &Animal, observation : Observation, rng: &mut oorandom::Rand32) -> Decision;
+
+    fn learn(&mut self, _outcome : &Outcome) {}
+
+    // 一局结束 (死光, 到 tick 上限或破产) 之后调用一次; 按局学习的策略在这里更新.
+    fn end_episode(&mut self) {}
+
+    fn mutate(&self, _mutate_factor:u32, _rng :&mut oorandom::Rand32) -> Self {
+        self.clone()
+    }
+
+    fn reward(&self, _reward_factor:u32) -> Self {
+        self.clone()
+    }
+
+    // 遗传算法里两个个体的交叉; 默认不交叉, 直接复制第一个.
+    fn crossover(&self, _other: &Self, _rng :&mut oorandom::Rand32) -> Self {
+        self.clone()
+    }
+
+    // 清掉跨局累积的记录, 只保留学到的东西.
+    fn clear_history(&mut self) {}
+
+    // 设置探索程度 (temperature 或 epsilon), 训练时按 run 退火; 不探索的策略忽略.
+    fn set_exploration(&mut self, _exploration : f64) {}
+
+    // 做过的决定, 用来重放; 不记录的策略返回空.
+    fn decision_history(&self) -> &[(u128, StateKey, Decision)] {
+        &[]
+    }
+
+    // 人能读懂的策略结构, 没有的返回 None.
+    fn explain(&self) -> Option<String> {
+        None
+    }
+
+    fn from_json(_path_name: String) -> Result<Self, PolicyError> {
+        policy_from_json(_path_name)
+    }
+
+    fn to_json(&self, json_path:String) -> Result<(), PolicyError> {
+        policy_to_json(self, json_path)
+    }
+}
+
+impl Policy for DecisionMakingTree {
+    fn make_a_decision(&mut self, tick : u128, a: &Animal, observation : Observation, rng: &mut oorandom::Rand32) -> Decision {
+        DecisionMakingTree::make_a_decision(self, tick, a, observation, rng)
+    }
+
+    fn learn(&mut self, outcome : &Outcome) {
+        self.outcome_history.push((outcome.tick, outcome.hp_delta + outcome.ability_delta, outcome.alive));
+    }
+
+    fn mutate(&self, _mutate_factor:u32, rng :&mut oorandom::Rand32) -> Self {
+        DecisionMakingTree::mutate(self, _mutate_factor, rng)
+    }
+
+    fn reward(&self, _reward_factor:u32) -> Self {
+        DecisionMakingTree::reward(self, _reward_factor)
+    }
+
+    fn crossover(&self, _other: &Self, rng :&mut oorandom::Rand32) -> Self {
+        DecisionMakingTree::crossover(self, _other, rng)
+    }
+
+    fn clear_history(&mut self) {
+        self.decision_history = vec![];
+        self.outcome_history = vec![];
+    }
+
+    fn set_exploration(&mut self, exploration : f64) {
+        self.sampling = self.sampling.with_exploration(exploration);
+    }
+
+    fn decision_history(&self) -> &[(u128, StateKey, Decision)] {
+        &self.decision_history
+    }
+
+    fn from_json(_path_name: String) -> Result<Self, PolicyError> {
+        DecisionMakingTree::from_json(_path_name)
+    }
+
+    fn to_json(&self, json_path:String) -> Result<(), PolicyError> {
+        DecisionMakingTree::to_json(self, json_path)
+    }
+}
+
+// 决策树的状态 key: 排好序的 (DecisionFactor, 出现次数) 多重集合,
+// 与环境在 Vec<Environment> 里的存储顺序无关.
+pub type StateKey = Vec<(DecisionFactor, u32)>;
+
+pub fn canonical_key(vdf : Vec<DecisionFactor>) -> StateKey {
+    let mut counts : BTreeMap<DecisionFactor, u32> = BTreeMap::new();
+    for df in vdf {
+        *counts.entry(df).or_insert(0) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+// reward 时怎么把奖励分给 decision_history 里的每一条.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum RewardMode {
+    // 每条记录都加 reward_factor
+    Uniform,
+    // 每条记录按它之后这一局的折扣回报加减权重, 死前的决定扣 death_penalty
+    Discounted {
+        discount : f64,
+        death_penalty : i32,
+    },
+}
+
+impl RewardMode {
+    pub fn uniform() -> Self {
+        RewardMode::Uniform
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DecisionMakingTree {
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub decision_chain : HashMap<StateKey, DecisionMaker>,
+    // FIXME 历史记录
+    pub decision_history : Vec<(u128, StateKey, Decision)>,
+    #[serde(default = "StateAbstraction::raw")]
+    pub abstraction : StateAbstraction,
+    #[serde(default = "RewardMode::uniform")]
+    pub reward_mode : RewardMode,
+    #[serde(default = "SamplingMode::proportional")]
+    pub sampling : SamplingMode,
+    // 每个决定执行后的 (tick, hp + ability 变化, 是否还活着), 与 decision_history 的末尾对齐;
+    // 旧的记录没有结果, 所以可能比 decision_history 短.
+    #[serde(default)]
+    pub outcome_history : Vec<(u128, i32, bool)>,
+}
+
+// 旧格式的 key 是有序的 Vec<DecisionFactor>, 读入时转换成 StateKey,
+// 转换后相同的 key 对应的 DecisionMaker 取平均合并.
+// 旧 key 是按修正前的方向计算和当时的 Raw factor 生成的, 现在的 Raw 几乎不会再生成同样的 key:
+// 迁移只是让旧文件还能读, 里面学到的权重保留在文件里但实际上查不到.
+#[derive(Deserialize)]
+struct LegacyDecisionMakingTree {
+    decision_chain : Vec<(Vec<DecisionFactor>, DecisionMaker)>,
+    decision_history : Vec<(u128, Vec<DecisionFactor>, Decision)>,
+    #[serde(default = "StateAbstraction::raw")]
+    abstraction : StateAbstraction,
+}
+
+impl LegacyDecisionMakingTree {
+    fn migrate(self) -> DecisionMakingTree {
+        let mut merged : HashMap<StateKey, Vec<DecisionMaker>> = HashMap::new();
+        for (vdf, dm) in self.decision_chain {
+            merged.entry(canonical_key(vdf)).or_default().push(dm);
+        }
+        DecisionMakingTree {
+            decision_chain : merged.into_iter().map(|(key, vdm)| (key, DecisionMaker::average(&vdm))).collect(),
+            decision_history : self.decision_history.into_iter()
+                .map(|(tick, vdf, d)| (tick, canonical_key(vdf), d))
+                .collect(),
+            abstraction : self.abstraction,
+            reward_mode : RewardMode::Uniform,
+            sampling : SamplingMode::proportional(),
+            outcome_history : vec![],
+        }
+    }
+}
+
+impl Default for DecisionMakingTree {
+    fn default() -> Self {
+        DecisionMakingTree::new()
+    }
+}
+
+impl DecisionMakingTree {
+    pub fn new() -> DecisionMakingTree {
+        DecisionMakingTree::with_abstraction(StateAbstraction::default())
+    }
+
+    pub fn with_abstraction(abstraction : StateAbstraction) -> DecisionMakingTree {
+        DecisionMakingTree{
+            decision_history:vec!(),
+            decision_chain:HashMap::new(),
+            abstraction,
+            reward_mode : RewardMode::Uniform,
+            sampling : SamplingMode::proportional(),
+            outcome_history : vec![],
+        }
+    }
+
+    pub fn from_json(_path_name: String) -> Result<DecisionMakingTree, PolicyError> {
+        let serialized = read_policy_file(&_path_name)?;
+        let err = match serde_json::from_str(serialized.as_str()) {
+            Ok(decision_making_tree) => return Ok(decision_making_tree),
+            Err(err) => err,
+        };
+        if err.classify() != Category::Data {
+            return Err(PolicyError::MalformedJson(_path_name, err));
+        }
+        match serde_json::from_str::<LegacyDecisionMakingTree>(serialized.as_str()) {
+            Ok(legacy) => Ok(legacy.migrate()),
+            Err(_) => Err(PolicyError::IncompatibleSchema(_path_name, short_type_name::<DecisionMakingTree>(), err)),
+        }
+    }
+
+    // pub fn init_json() {
+    //     let dmt = DecisionMakingTree{decision_chain:HashMap::new(),decision_history:vec![]};
+    //     let path = Path::new("decision_making_tree.json");
+    //     let mut file = File::create(&path).unwrap();
+    //     let serialized = serde_json::to_string(&dmt).unwrap();
+    //     file.write(serialized.as_bytes()).unwrap();
+    // }
+
+    pub fn to_json(&self, json_path:String) -> Result<(), PolicyError> {
+        policy_to_json(self, json_path)
+    }
+    // FIXME 实现
+    fn mutate_impl(mut self, _mutate_factor:u32, rng :&mut oorandom::Rand32) -> DecisionMakingTree {
+        for dm in self.decision_chain.values_mut() {
+            dm.mutate_chance(_mutate_factor, rng);
+        }
+        self
+    }
+
+    fn reward_impl(mut self, _reward_factor:u32) -> DecisionMakingTree {
+        match self.reward_mode {
+            RewardMode::Uniform => {
+                for (_, vdf, dis) in &self.decision_history {
+                    self.decision_chain.get_mut(vdf).unwrap().increase_chance(*dis, _reward_factor);
+                }
+            },
+            RewardMode::Discounted { discount, death_penalty } => {
+                let returns = self.discounted_returns(discount, death_penalty);
+                for ((_, vdf, dis), g) in self.decision_history.iter().zip(returns) {
+                    let delta = (g * _reward_factor as f64).round() as i32;
+                    let dm = self.decision_chain.get_mut(vdf).unwrap();
+                    if delta > 0 {
+                        dm.increase_chance(*dis, delta as u32);
+                    } else if delta < 0 {
+                        dm.decrease_chance(*dis, (-delta) as u32);
+                    }
+                }
+            },
+        }
+        self
+    }
+
+    // 每条记录的折扣回报 G_t = r_t + discount * G_{t+1}, 按局计算 (tick 归零处是新的一局).
+    // r_t 把活着每个 tick 固定消耗的 1 点 hp 加回去, 只留下决定本身带来的变化;
+    // 死亡的那一步再扣 death_penalty. 没有结果的旧记录 r_t 为 0.
+    // outcome_history 比 decision_history 短时对齐的是最后几条记录.
+    // 只适合一只动物的历史: 共用策略时多只动物的记录交错在一起, 同一局里有好几条 tick 0,
+    // 会被切成很多很短的局, 回报也会算到别的动物的决定上.
+    fn discounted_returns(&self, discount : f64, death_penalty : i32) -> Vec<f64> {
+        let len = self.decision_history.len();
+        let offset = len.saturating_sub(self.outcome_history.len());
+        let mut returns = vec![0.0; len];
+        let mut g = 0.0;
+        for i in (0..len).rev() {
+            let episode_end = i + 1 == len || self.decision_history[i + 1].0 == 0;
+            if episode_end {
+                g = 0.0;
+            }
+            let r = if i >= offset {
+                let (_, delta, alive) = self.outcome_history[i - offset];
+                (delta + 1 - if alive { 0 } else { death_penalty }) as f64
+            } else {
+                0.0
+            };
+            g = r + discount * g;
+            returns[i] = g;
+        }
+        returns
+    }
+
+    pub fn reward(&self, _reward_factor:u32) -> DecisionMakingTree {
+        self.clone().reward_impl(_reward_factor)
+    }
+
+    pub fn mutate(&self, _mutate_factor:u32, rng :&mut oorandom::Rand32) -> DecisionMakingTree {
+        self.clone().mutate_impl(_mutate_factor, rng)
+    }
+
+    // 按状态 key 混合两棵树: 两边都有的 key 随机取一边的 DecisionMaker, 只有一边有的直接保留.
+    // key 排序后再遍历, 同一个种子得到同样的孩子.
+    pub fn crossover(&self, other: &DecisionMakingTree, rng :&mut oorandom::Rand32) -> DecisionMakingTree {
+        let mut keys : Vec<&StateKey> = self.decision_chain.keys().chain(other.decision_chain.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let mut decision_chain = HashMap::new();
+        for key in keys {
+            let dm = match (self.decision_chain.get(key), other.decision_chain.get(key)) {
+                (Some(a), Some(b)) => if rng.rand_u32() % 2 == 0 { a } else { b },
+                (Some(a), None) => a,
+                (None, Some(b)) => b,
+                (None, None) => continue,
+            };
+            decision_chain.insert(key.clone(), dm.clone());
+        }
+        DecisionMakingTree {
+            decision_chain,
+            decision_history : vec![],
+            abstraction : self.abstraction.clone(),
+            reward_mode : self.reward_mode,
+            sampling : self.sampling,
+            outcome_history : vec![],
+        }
+    }
+
+    fn generate_default_decision_maker(&mut self, key : StateKey) -> DecisionMaker {
+        self.decision_chain.insert(key, DecisionMaker::default());
+        DecisionMaker::default()
+    }
+
+    fn get_decision_maker(&mut self, key : StateKey) -> DecisionMaker {
+        let decision_chain_get = self.decision_chain.get(&key);
+        match decision_chain_get {
+            Some(d_maker) => d_maker.clone(),
+            None => self.generate_default_decision_maker(key),
+        }
+    }
+
+    fn calculate_decision_factors(&mut self, a: &Animal, observation : Observation) -> StateKey {
+        canonical_key(self.abstraction.summarize(a, observation))
+    }
+
+    pub fn make_a_decision(&mut self, tick : u128, a: &Animal, observation : Observation, rng: &mut oorandom::Rand32) -> Decision {
+        let key = self.calculate_decision_factors(a, observation);
+        let kc = key.clone();
+        let decision = self.make_a_decision_impl(key, rng);
+        self.decision_history.push((tick, kc, decision));
+        decision
+    }
+
+    fn make_a_decision_impl(&mut self, key : StateKey, rng:&mut oorandom::Rand32) -> Decision {
+        let decision_maker = self.get_decision_maker(key);
+        decision_maker.make_decision(&self.sampling, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldConfig;
+
+    fn legacy_maker(wait : f64) -> DecisionMaker {
+        let mut dm = DecisionMaker::default();
+        dm.decision_map.insert(Decision::Wait, wait);
+        dm
+    }
+
+    #[test]
+    fn keys_ignore_environment_order() {
+        let world = WorldConfig::default();
+        let a = world.animals[0].spwan((5, 5));
+        let ve : Vec<Environment> = vec![
+            world.environments[0].spwan((5, 6)),
+            world.environments[1].spwan((3, 5)),
+            world.environments[2].spwan((5, 5)),
+            world.environments[0].spwan((7, 5)),
+        ];
+        let mut reversed = ve.clone();
+        reversed.reverse();
+        for abstraction in [StateAbstraction::Raw, StateAbstraction::default()] {
+            assert_eq!(
+                canonical_key(abstraction.summarize(&a, Observation { environments : ve.clone(), ..Observation::default() })),
+                canonical_key(abstraction.summarize(&a, Observation { environments : reversed.clone(), ..Observation::default() })));
+        }
+    }
+
+    #[test]
+    fn legacy_keys_collapsing_to_one_are_averaged() {
+        let up = DecisionFactor::DistanceDirection(1, Direction::Up, EnvironmentTag::SHELTER);
+        let down = DecisionFactor::DistanceDirection(2, Direction::Dowm, EnvironmentTag::DANGER);
+        let legacy = LegacyDecisionMakingTree {
+            decision_chain : vec![
+                (vec![up.clone(), down.clone()], legacy_maker(4.0)),
+                (vec![down.clone(), up.clone()], legacy_maker(8.0)),
+            ],
+            decision_history : vec![(0, vec![down.clone(), up.clone()], Decision::Wait)],
+            abstraction : StateAbstraction::raw(),
+        };
+        let tree = legacy.migrate();
+        let key = canonical_key(vec![up, down]);
+        assert_eq!(tree.decision_chain.len(), 1);
+        assert_eq!(tree.decision_chain[&key].decision_map[&Decision::Wait], 6.0);
+        assert_eq!(tree.decision_chain[&key].decision_map[&Decision::MoveUp], 2.0);
+        assert_eq!(tree.decision_history, vec![(0, key, Decision::Wait)]);
+    }
+
+    fn history(ticks : &[u128], outcomes : &[(u128, i32, bool)]) -> DecisionMakingTree {
+        let mut tree = DecisionMakingTree::new();
+        tree.decision_history = ticks.iter().map(|tick| (*tick, vec![], Decision::Wait)).collect();
+        tree.outcome_history = outcomes.to_vec();
+        tree
+    }
+
+    #[test]
+    fn discounted_returns_restart_at_tick_zero() {
+        let tree = history(&[0, 1, 0, 1], &[(0, -1, true), (1, -1, false), (0, -1, true), (1, 0, true)]);
+        assert_eq!(tree.discounted_returns(0.5, 10), vec![-5.0, -10.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn discounted_returns_align_outcomes_with_the_latest_decisions() {
+        let tree = history(&[0, 1, 2], &[(1, -1, true), (2, 3, true)]);
+        assert_eq!(tree.discounted_returns(0.5, 10), vec![1.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn softmax_explores_zero_weights() {
+        let dm = DecisionMaker::default();
+        let softmax = dm.probabilities(1.0);
+        assert!(softmax[&Decision::Build] > 0.0);
+        assert!((softmax.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((softmax[&Decision::Wait] / softmax[&Decision::MoveUp] - (8.0f64).exp()).abs() < 1e-6);
+        assert_eq!(dm.proportional_probabilities()[&Decision::Build], 0.0);
+        assert_eq!(dm.probabilities(0.0)[&Decision::Wait], 1.0);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap};
+use serde_derive::{Serialize,Deserialize};
+use serde_with::serde_as;
+
+use crate::abstraction::StateAbstraction;
+use crate::policy::{canonical_key, Decision, Observation, Outcome, Policy, StateKey, DECISIONS};
+use crate::simulation::Animal;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UpdateRule {
+    // 用下一个状态里最好的决定估值 (off-policy)
+    QLearning,
+    // 用下一个状态里实际做出的决定估值 (on-policy)
+    Sarsa,
+}
+
+// 以 DecisionMakingTree 相同的 StateKey 为状态的表格 Q-learning / SARSA.
+// 每个 tick 的奖励是 hp 和 ability 的变化, 死亡额外扣 death_penalty.
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QLearningPolicy {
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub q_table : HashMap<StateKey, BTreeMap<Decision, f64>>,
+    pub decision_history : Vec<(u128, StateKey, Decision)>,
+    pub abstraction : StateAbstraction,
+    pub update_rule : UpdateRule,
+    pub learning_rate : f64,
+    pub discount : f64,
+    pub epsilon : f64,
+    pub death_penalty : f64,
+    // 每只动物上一个 tick 的 (状态, 决定, 奖励), 等它看到下一个状态后再更新.
+    #[serde(skip)]
+    pending : HashMap<usize, (StateKey, Decision, f64)>,
+}
+
+impl QLearningPolicy {
+    pub fn new(abstraction : StateAbstraction, update_rule : UpdateRule, learning_rate : f64, discount : f64, epsilon : f64) -> Self {
+        QLearningPolicy {
+            q_table : HashMap::new(),
+            decision_history : vec![],
+            abstraction,
+            update_rule,
+            learning_rate,
+            discount,
+            epsilon,
+            death_penalty : 10.0,
+            pending : HashMap::new(),
+        }
+    }
+
+    fn q_values(&mut self, key : &StateKey) -> &mut BTreeMap<Decision, f64> {
+        self.q_table.entry(key.clone()).or_insert_with(|| {
+            DECISIONS.iter().map(|d| (*d, 0.0)).collect()
+        })
+    }
+
+    fn q_value(&mut self, key : &StateKey, d : Decision) -> f64 {
+        *self.q_values(key).get(&d).unwrap_or(&0.0)
+    }
+
+    fn max_q_value(&mut self, key : &StateKey) -> f64 {
+        self.q_values(key).values().cloned().fold(f64::MIN, f64::max)
+    }
+
+    fn update(&mut self, key : &StateKey, d : Decision, target : f64) {
+        let learning_rate = self.learning_rate;
+        let q = self.q_values(key).entry(d).or_insert(0.0);
+        *q += learning_rate * (target - *q);
+    }
+
+    // 最大值相同的决定里随机选一个, 免得没学过的状态总是选第一个.
+    fn greedy_decision(&mut self, key : &StateKey, rng : &mut oorandom::Rand32) -> Decision {
+        let q_values = self.q_values(key);
+        let best = q_values.values().cloned().fold(f64::MIN, f64::max);
+        let candidates : Vec<Decision> = q_values.iter()
+            .filter(|(_, q)| **q >= best)
+            .map(|(d, _)| *d)
+            .collect();
+        candidates[rng.rand_range(0..candidates.len() as u32) as usize]
+    }
+
+    fn epsilon_greedy_decision(&mut self, key : &StateKey, rng : &mut oorandom::Rand32) -> Decision {
+        if (rng.rand_float() as f64) < self.epsilon {
+            DECISIONS[rng.rand_range(0..DECISIONS.len() as u32) as usize]
+        } else {
+            self.greedy_decision(key, rng)
+        }
+    }
+}
+
+impl Policy for QLearningPolicy {
+    fn make_a_decision(&mut self, tick : u128, a: &Animal, observation : Observation, rng: &mut oorandom::Rand32) -> Decision {
+        let agent = observation.agent;
+        if tick == 0 {
+            // 新的一局, 上一局留下的待更新记录不能接到这一局上
+            self.pending.remove(&agent);
+        }
+        let key = canonical_key(self.abstraction.summarize(a, observation));
+        let decision = self.epsilon_greedy_decision(&key, rng);
+        if let Some((last_key, last_decision, reward)) = self.pending.remove(&agent) {
+            let next_value = match self.update_rule {
+                UpdateRule::QLearning => self.max_q_value(&key),
+                UpdateRule::Sarsa => self.q_value(&key, decision),
+            };
+            self.update(&last_key, last_decision, reward + self.discount * next_value);
+        }
+        self.pending.insert(agent, (key.clone(), decision, 0.0));
+        self.decision_history.push((tick, key, decision));
+        decision
+    }
+
+    fn learn(&mut self, outcome : &Outcome) {
+        let (key, decision, _) = match self.pending.remove(&outcome.agent) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let reward = (outcome.hp_delta + outcome.ability_delta) as f64;
+        if outcome.alive {
+            self.pending.insert(outcome.agent, (key, decision, reward));
+        } else {
+            // 死亡是终止状态, 没有后续的估值
+            self.update(&key, decision, reward - self.death_penalty);
+        }
+    }
+
+    // 一局结束时还活着的动物没有下一个状态了
+    fn end_episode(&mut self) {
+        self.pending.clear();
+    }
+
+    // 复制出来的策略 (比如新生的孩子) 不能接着学原来那份还没学完的记录
+    fn clear_history(&mut self) {
+        self.decision_history = vec![];
+        self.pending.clear();
+    }
+
+    fn set_exploration(&mut self, exploration : f64) {
+        self.epsilon = exploration;
+    }
+
+    fn decision_history(&self) -> &[(u128, StateKey, Decision)] {
+        &self.decision_history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldConfig;
+
+    #[test]
+    fn shared_policy_credits_each_animal() {
+        let world = WorldConfig::default();
+        let a = world.animals[0].spwan((5, 5));
+        let mut policy = QLearningPolicy::new(StateAbstraction::Raw, UpdateRule::QLearning, 1.0, 0.0, 0.0);
+        policy.death_penalty = 0.0;
+        let mut rng = oorandom::Rand32::new(1);
+        // 两只动物先后做决定, 再按同样的顺序拿到结果, 像 Agents::Shared 那样交错
+        let seen = Observation { agent : 0, environments : vec![world.environments[0].spwan((5, 6))], ..Observation::default() };
+        let unseen = Observation { agent : 1, ..Observation::default() };
+        let seen_key = canonical_key(policy.abstraction.summarize(&a, seen.clone()));
+        let unseen_key = canonical_key(policy.abstraction.summarize(&a, unseen.clone()));
+        assert_ne!(seen_key, unseen_key);
+        let seen_decision = policy.make_a_decision(0, &a, seen, &mut rng);
+        let unseen_decision = policy.make_a_decision(0, &a, unseen, &mut rng);
+        policy.learn(&Outcome { tick : 0, agent : 0, hp_delta : 5, ability_delta : 0, alive : false });
+        policy.learn(&Outcome { tick : 0, agent : 1, hp_delta : -3, ability_delta : 0, alive : false });
+        assert_eq!(policy.q_value(&seen_key, seen_decision), 5.0);
+        assert_eq!(policy.q_value(&unseen_key, unseen_decision), -3.0);
+        assert!(policy.pending.is_empty());
+    }
+
+    #[test]
+    fn cleared_copy_has_nothing_to_learn() {
+        let world = WorldConfig::default();
+        let a = world.animals[0].spwan((5, 5));
+        let mut parent = QLearningPolicy::new(StateAbstraction::Raw, UpdateRule::QLearning, 1.0, 0.0, 0.0);
+        let mut rng = oorandom::Rand32::new(1);
+        parent.make_a_decision(0, &a, Observation::default(), &mut rng);
+        let mut child = parent.clone();
+        child.clear_history();
+        child.learn(&Outcome { tick : 0, agent : 0, hp_delta : 5, ability_delta : 0, alive : false });
+        assert!(child.q_table.values().all(|q| q.values().all(|v| *v == 0.0)));
+        assert!(child.decision_history.is_empty());
+    }
+}
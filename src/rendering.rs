@@ -0,0 +1,179 @@
+#[cfg(feature = "gui")]
+use pixels::{Pixels, SurfaceTexture};
+#[cfg(feature = "gui")]
+use winit::{
+    dpi::LogicalSize,
+    // event::{Event, VirtualKeyCode},
+    event_loop::EventLoop,
+    window::{WindowBuilder, Window},
+};
+
+use crate::simulation::{WIDTH, HEIGHT};
+#[cfg(feature = "gui")]
+use crate::fields::{Field, ScalarFields};
+#[cfg(feature = "gui")]
+use crate::simulation::{Animal, Environment, DrawType};
+
+pub const WINDOW_WIDTH: u32 = 500;
+pub const WINDOW_HEIGHT: u32 = 500;
+pub const GRID_WIDTH: u32 = WINDOW_WIDTH/WIDTH;
+pub const GRID_HEIGHT: u32 = WINDOW_HEIGHT/HEIGHT;
+
+#[cfg(feature = "gui")]
+pub fn build_window() -> (EventLoop<()>, Window, Pixels) {
+    let event_loop = EventLoop::new();
+    // let input = WinitInputHelper::new();
+    let window = {
+        let size =
+            LogicalSize::new(WINDOW_WIDTH as f64, WINDOW_HEIGHT as f64);
+        let scaled_size =
+            LogicalSize::new(WINDOW_WIDTH as f64, WINDOW_HEIGHT as f64);
+        WindowBuilder::new()
+            .with_title("simulation visualization")
+            .with_inner_size(scaled_size)
+            .with_min_inner_size(size)
+            .build(&event_loop)
+            .unwrap()
+    };
+
+    let pixels = {
+        let window_size = window.inner_size();
+        let surface_texture =
+            SurfaceTexture::new(window_size.width, window_size.height, &window);
+        Pixels::new(WINDOW_WIDTH, WINDOW_HEIGHT, surface_texture).unwrap()
+    };
+    (event_loop, window, pixels)
+}
+
+#[cfg(feature = "gui")]
+pub fn visualize_map(ve : &Vec<Environment>, va : &Vec<Animal>, screen: &mut [u8]) {
+    for e in ve {
+        let pos = e.get_center_pixel_pos();
+        match e.draw_type {
+            DrawType::Round => draw_round(screen, pos.0, pos.1, e.d as i32,
+                e.color.0,e.color.1,e.color.2,e.color.3),
+            DrawType::Pixel => draw_pixel(screen, pos.0, pos.1,
+                e.color.0,e.color.1,e.color.2,e.color.3),
+            DrawType::Rect => draw_rect(screen, pos.0, pos.1, e.d as i32,
+                e.color.0,e.color.1,e.color.2,e.color.3),
+            _ => (),
+        }
+    }
+    visualize_utilities(ve, screen);
+    for a in va {
+        let pos = a.get_center_pixel_pos();
+        draw_star(screen, pos.0, pos.1, 0xff,0xff,0,0xff);
+    }
+}
+
+// 场的值每增加 1, 热图的颜色亮这么多.
+#[cfg(feature = "gui")]
+const HEAT_SCALE : f64 = 32.0;
+
+// 画在环境下面的热图: 红色是污染, 绿色是地价, 蓝色是犯罪; 三种场都接近 0 的格子不画.
+#[cfg(feature = "gui")]
+pub fn visualize_heatmap(fields : &ScalarFields, screen: &mut [u8]) {
+    let heat = |f, pos| f64::min(fields.get(f, pos) * HEAT_SCALE, 255.0) as u8;
+    for x in 0..fields.map_size.0 {
+        for y in 0..fields.map_size.1 {
+            let (r, g, b) = (heat(Field::Pollution, (x, y)), heat(Field::LandValue, (x, y)), heat(Field::Crime, (x, y)));
+            if r == 0 && g == 0 && b == 0 {
+                continue;
+            }
+            let center = (((GRID_WIDTH + 1) / 2 + GRID_WIDTH * x as u32) as i32, ((GRID_HEIGHT + 1) / 2 + GRID_HEIGHT * y as u32) as i32);
+            draw_rect(screen, center.0, center.1, GRID_WIDTH as i32, r, g, b, 0xff);
+        }
+    }
+}
+
+// 在消费者格子的左上角画一个小方块: 有 utility 服务是绿色, 没有是红色.
+#[cfg(feature = "gui")]
+pub fn visualize_utilities(ve : &[Environment], screen: &mut [u8]) {
+    for e in ve.iter().filter(|e| !e.consumes.is_empty()) {
+        let pos = e.get_center_pixel_pos();
+        let (r, g) = if e.serviced { (0, 0xff) } else { (0xff, 0) };
+        draw_rect(screen, pos.0 - GRID_WIDTH as i32 / 3, pos.1 - GRID_HEIGHT as i32 / 3, 3, r, g, 0, 0xff);
+    }
+}
+
+#[cfg(feature = "gui")]
+const STAR : [(i32, i32); 76]= [
+    (-6,0),(-6,-1),
+    (-5,0),(-5,-1),(-5,3),(-5,4),(-5,-4),(-5,-5),
+    (-4,0),(-4,2),(-4,3),(-4,4),(-4,-1),(-4,-3),(-4,-4),(-4,-5),
+    (-3,1),(-3,2),(-3,-2),(-3,-3),(-3,0),(-3,-1),(-3,3),(-3,-1),
+    (-2,1),(-2,2),(-2,-2),(-2,-3),
+    (-1,0),(-1,-1),(-1,-3),(-1,-4),(-1,-5),(-1,-6),(-1,2),(-1,3),(-1,4),(-1,5),
+    (0,0),(0,-1),(0,-3),(0,-4),(0,-5),(0,-6),(0,2),(0,3),(0,4),(0,5),
+    (1,1),(1,2),(1,-2),(1,-3),
+    (2,1),(2,2),(2,-2),(2,-3),(2,0),(2,-1),(2,3),(2,-1),
+    (3,0),(3,2),(3,3),(3,4),(3,-1),(3,-3),(3,-4),(3,-5),
+    (4,0),(4,-1),(4,3),(4,4),(4,-4),(4,-5),
+    (5,0),(5,-1),
+];
+
+#[cfg(feature = "gui")]
+pub fn draw_star(screen: &mut [u8], x:i32, y:i32, r:u8,g:u8,b:u8,a:u8) {
+    for (i, j) in STAR {
+        draw_pixel(screen, x + i + 1, y + j + 1, r, g, b, a);
+    }
+}
+
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn draw_rect(screen: &mut [u8], x:i32, y:i32, d:i32,
+    r:u8,g:u8,b:u8,a:u8) {
+    let half_d = (d + 1) / 2;
+    for i in 0..half_d {
+        for j in 0..half_d {
+            draw_pixel(screen, x - i, y - j, r, g, b, a);
+            draw_pixel(screen, x - i, y + j, r, g, b, a);
+            draw_pixel(screen, x + i, y - j, r, g, b, a);
+            draw_pixel(screen, x + i, y + j, r, g, b, a);
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+#[allow(clippy::too_many_arguments)]
+pub fn draw_round(screen: &mut [u8], x:i32, y:i32, d:i32,
+    r:u8,g:u8,b:u8,a:u8) {
+    let half_d = (d + 1) / 2;
+    let sqr_half_d = half_d * half_d;
+
+    for i in 0..half_d {
+        for j in 0..half_d {
+            if (i*i + j*j) <= sqr_half_d {
+                draw_pixel(screen, x - i, y - j, r, g, b, a);
+                draw_pixel(screen, x - i, y + j, r, g, b, a);
+                draw_pixel(screen, x + i, y - j, r, g, b, a);
+                draw_pixel(screen, x + i, y + j, r, g, b, a);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+pub fn draw_pixel(screen: &mut [u8], x:i32, y:i32, r:u8,g:u8,b:u8,a:u8) {
+    if x<0 || y<0 || x * WINDOW_HEIGHT as i32 * 4 + y * 4 + 3 >= screen.len() as i32 {
+        return;
+    }
+    let x = x as usize;
+    let y = y as usize;
+    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4] = r;
+    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4 + 1] = g;
+    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4 + 2] = b;
+    screen[x * WINDOW_HEIGHT as usize * 4 + y * 4 + 3] = a;
+}
+
+#[cfg(feature = "gui")]
+pub fn clear_pixels(pixels : &mut [u8]) {
+    for i in 0..WINDOW_WIDTH as usize {
+        for j in 0..WINDOW_HEIGHT as usize {
+            pixels[4 * (i * WINDOW_HEIGHT as usize + j)] = 0;
+            pixels[4 * (i * WINDOW_HEIGHT as usize + j) + 1] = 0;
+            pixels[4 * (i * WINDOW_HEIGHT as usize + j) + 2] = 0;
+            pixels[4 * (i * WINDOW_HEIGHT as usize + j) + 3] = 0;
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use serde_derive::{Serialize,Deserialize};
+
+use crate::simulation::Environment;
+
+// 世界配置里预先铺好的路: from 到 to 的一段横线或竖线, 用 template 这个环境模板.
+// 只有碰到地图边缘的路才通向外面, requires_road 的建筑要挨着这样的路才有效果,
+// 所以至少要有一段路铺到边缘.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoadConfig {
+    pub template : String,
+    pub segments : Vec<((i32, i32), (i32, i32))>,
+}
+
+impl RoadConfig {
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        let mut cells = vec![];
+        for (from, to) in &self.segments {
+            let (x0, x1) = (i32::min(from.0, to.0), i32::max(from.0, to.0));
+            let (y0, y1) = (i32::min(from.1, to.1), i32::max(from.1, to.1));
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    if !cells.contains(&(x, y)) {
+                        cells.push((x, y));
+                    }
+                }
+            }
+        }
+        cells
+    }
+}
+
+pub const NEIGHBOURS : [(i32, i32); 4] = [(1, 0), (-1, 0), (0, -1), (0, 1)];
+
+pub fn is_road(ve : &[Environment], pos : (i32, i32)) -> bool {
+    ve.iter().any(|e| e.alive && e.road && e.position == pos)
+}
+
+// 路面格子按上下左右连成的图, 每个连通块一个编号.
+// 碰到地图边缘的连通块通向外面的世界, 只有挨着这些路的建筑才算接上了路网.
+pub struct RoadNetwork {
+    component : HashMap<(i32, i32), usize>,
+    outside : HashSet<usize>,
+}
+
+impl RoadNetwork {
+    pub fn build(ve : &[Environment], map_size : (i32, i32)) -> Self {
+        let roads : HashSet<(i32, i32)> = ve.iter().filter(|e| e.alive && e.road).map(|e| e.position).collect();
+        let mut starts : Vec<(i32, i32)> = roads.iter().cloned().collect();
+        starts.sort();
+        let mut component = HashMap::new();
+        let mut outside = HashSet::new();
+        let mut count = 0;
+        for start in starts {
+            if component.contains_key(&start) {
+                continue;
+            }
+            let id = count;
+            count += 1;
+            let mut queue = VecDeque::from(vec![start]);
+            component.insert(start, id);
+            while let Some(pos) = queue.pop_front() {
+                if pos.0 == 0 || pos.1 == 0 || pos.0 == map_size.0 - 1 || pos.1 == map_size.1 - 1 {
+                    outside.insert(id);
+                }
+                for inc in NEIGHBOURS {
+                    let next = (pos.0 + inc.0, pos.1 + inc.1);
+                    if roads.contains(&next) && !component.contains_key(&next) {
+                        component.insert(next, id);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        RoadNetwork { component, outside }
+    }
+
+    // 格子本身或者上下左右相邻的格子是通向外面的路.
+    pub fn connected(&self, pos : (i32, i32)) -> bool {
+        std::iter::once((0, 0)).chain(NEIGHBOURS).any(|inc| {
+            let next = (pos.0 + inc.0, pos.1 + inc.1);
+            self.component.get(&next).is_some_and(|id| self.outside.contains(id))
+        })
+    }
+}
+
+// 每个 tick 在决定之前刷新需要路的环境是否接上了路网.
+pub fn update_road_access(ve : &mut [Environment], map_size : (i32, i32)) {
+    if !ve.iter().any(|e| e.requires_road) {
+        return;
+    }
+    let network = RoadNetwork::build(ve, map_size);
+    for e in ve.iter_mut().filter(|e| e.requires_road) {
+        e.connected = network.connected(e.position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::blank_environment;
+
+    fn roads(cells : &[(i32, i32)]) -> Vec<Environment> {
+        cells.iter().map(|pos| {
+            let mut e = blank_environment(*pos);
+            e.road = true;
+            e
+        }).collect()
+    }
+
+    #[test]
+    fn only_roads_reaching_the_edge_are_connected() {
+        let ve = roads(&[(0, 3), (1, 3), (2, 3), (6, 6), (6, 7)]);
+        let network = RoadNetwork::build(&ve, (10, 10));
+        assert!(network.connected((2, 3)));
+        assert!(network.connected((3, 3)));
+        assert!(network.connected((1, 4)));
+        assert!(!network.connected((3, 4)));
+        assert!(!network.connected((6, 6)));
+        assert!(!network.connected((5, 7)));
+    }
+
+    #[test]
+    fn dead_roads_are_not_part_of_the_network() {
+        let mut ve = roads(&[(0, 3), (1, 3), (2, 3)]);
+        ve[1].alive = false;
+        let network = RoadNetwork::build(&ve, (10, 10));
+        assert!(network.connected((0, 4)));
+        assert!(!network.connected((1, 4)));
+        assert!(!network.connected((3, 3)));
+    }
+}
@@ -0,0 +1,673 @@
+use serde_derive::{Serialize,Deserialize};
+
+use crate::budget::Treasury;
+use crate::fields::Field;
+use crate::policy::Decision;
+use crate::rendering::{GRID_WIDTH, GRID_HEIGHT};
+use crate::world::{EnvironmentTemplate, Reproduction, WorldConfig};
+use crate::roads::is_road;
+use crate::utilities::Utility;
+use crate::zoning::Zone;
+
+// 地图的最大尺寸, 也是可视化网格的尺寸; WorldConfig 里的尺寸不能超过它.
+pub const WIDTH: u32 = 50;
+pub const HEIGHT: u32 = 50;
+
+pub trait Tickable {
+    fn tick(&mut self);
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    Food,
+    Wood,
+    Stone,
+}
+
+pub const RESOURCES : [Resource; 3] = [Resource::Food, Resource::Wood, Resource::Stone];
+
+// 吃一份食物回复的 hp.
+pub const FOOD_HP: i32 = 3;
+
+// 动物背包里的资源, 也用来写环境的产出和蓝图的资源花费.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Inventory {
+    #[serde(default)]
+    pub food : u32,
+    #[serde(default)]
+    pub wood : u32,
+    #[serde(default)]
+    pub stone : u32,
+}
+
+impl Inventory {
+    pub fn get(&self, r : Resource) -> u32 {
+        match r {
+            Resource::Food => self.food,
+            Resource::Wood => self.wood,
+            Resource::Stone => self.stone,
+        }
+    }
+
+    fn get_mut(&mut self, r : Resource) -> &mut u32 {
+        match r {
+            Resource::Food => &mut self.food,
+            Resource::Wood => &mut self.wood,
+            Resource::Stone => &mut self.stone,
+        }
+    }
+
+    pub fn add(&mut self, other : &Inventory) {
+        for r in RESOURCES {
+            *self.get_mut(r) += other.get(r);
+        }
+    }
+
+    pub fn covers(&self, cost : &Inventory) -> bool {
+        RESOURCES.iter().all(|r| self.get(*r) >= cost.get(*r))
+    }
+
+    // 调用前先用 covers 检查够不够.
+    pub fn take(&mut self, cost : &Inventory) {
+        for r in RESOURCES {
+            *self.get_mut(r) -= cost.get(r);
+        }
+    }
+}
+
+// 做决定, 执行决定, 移动, 都超出了类Animal的可视范围.
+#[derive(Clone, Copy, Debug)]
+pub struct Animal {
+    pub alive : bool,
+    pub hp : i32,
+    pub ability : u32,
+    pub lifetime : u32,
+    pub position : (i32, i32),
+    pub view_distance: u32,
+    pub next_decision : Decision,
+    // WorldConfig.animals 里模板的下标, 同一个模板生成的动物是同一物种
+    pub species : u32,
+    // Build 时用的蓝图, WorldConfig::blueprints 里的下标
+    pub blueprint : u32,
+    pub inventory : Inventory,
+}
+
+impl Animal {
+    pub fn spwan(a : &Animal, pos:(i32,i32)) ->Animal {
+        Animal {
+            alive : true,
+            hp : a.hp,
+            ability : a.ability,
+            lifetime : 0,
+            position : pos,
+            view_distance: a.view_distance,
+            next_decision : a.next_decision,
+            species : a.species,
+            blueprint : 0,
+            inventory : Inventory::default(),
+        }
+    }
+
+    fn consume(&mut self, num : i32) {
+        if !self.alive {
+            return
+        }
+        self.hp -= num;
+        if self.hp <= 0 {
+            self.alive = false;
+        }
+    }
+
+    pub fn move_inc(&mut self, inc:(i32, i32), map_size:(i32, i32)) {
+        self.position.0 += inc.0;
+        self.position.1 += inc.1;
+        if self.position.0 < 0 {
+            self.position.0 = 0;
+        }
+        if self.position.0 >= map_size.0 {
+            self.position.0 = map_size.0 - 1;
+        }
+
+        if self.position.1 < 0 {
+            self.position.1 = 0;
+        }
+        if self.position.1 >= map_size.1 {
+            self.position.1 = map_size.1 - 1;
+        }
+
+    }
+
+    // 和 move_inc 一样, 但是目标格子被挡住时留在原地.
+    pub fn move_inc_blocked(&mut self, inc:(i32, i32), map_size:(i32, i32), ve: &[Environment]) {
+        let mut moved = *self;
+        moved.move_inc(inc, map_size);
+        if !is_blocked(ve, moved.position) {
+            self.position = moved.position;
+        }
+    }
+
+    // 走一步; 从路上走到路上时再沿着同一个方向多走一步, 前面还是路的话.
+    pub fn move_inc_road(&mut self, inc:(i32, i32), map_size:(i32, i32), ve: &[Environment]) {
+        let start = self.position;
+        self.move_inc_blocked(inc, map_size, ve);
+        if self.position == start || !is_road(ve, start) || !is_road(ve, self.position) {
+            return;
+        }
+        let mut next = *self;
+        next.move_inc_blocked(inc, map_size, ve);
+        if is_road(ve, next.position) {
+            self.position = next.position;
+        }
+    }
+
+    pub fn get_center_pixel_pos(&self) -> (i32, i32) {
+        (((GRID_WIDTH + 1) / 2 + GRID_WIDTH * self.position.0 as u32) as i32,
+        ((GRID_HEIGHT + 1) / 2 + GRID_HEIGHT * self.position.1 as u32) as i32)
+    }
+}
+
+impl Tickable for Animal {
+    fn tick(&mut self) {
+        if !self.alive {
+            return;
+        }
+        self.lifetime += 1;
+        self.consume(1);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EnvironmentTag {
+    DANGER,
+    CHALLENGE,
+    SHELTER,
+    DEFAULT,
+    // 墙, 水之类动物走不过去的环境
+    OBSTACLE,
+    ROAD,
+    // 发电厂, 水塔和它们的管线
+    UTILITY,
+}
+
+#[derive(Clone, Debug)]
+pub struct Environment {
+    pub tag : EnvironmentTag,
+    pub alive : bool,
+    pub auto_interact : bool,
+    pub hp: i32,
+    pub difficulty : u32,
+    pub penalty : u32,
+    pub reward : (u32, u32),
+    pub position: (i32, i32),
+    // 交互成功时除了 reward 之外给的资源
+    pub yields : Inventory,
+    pub color : (u8, u8, u8, u8),
+    pub draw_type : DrawType,
+    pub d : u32,
+    // 动物不能走进这个环境所在的格子
+    pub blocking : bool,
+    // 由区划长出来的建筑属于哪种区
+    pub zone : Option<Zone>,
+    // 路面, 连成路网; 沿着路走一个 tick 能走两格
+    pub road : bool,
+    // 只有 connected (接上通向外面的路网) 时交互才有效果, 由 update_road_access 每个 tick 刷新
+    pub requires_road : bool,
+    pub connected : bool,
+    // 产出某种 utility, 或者是传送它的管线; 服务半径内要这种 utility 的消费者都有服务
+    pub produces : Option<Utility>,
+    pub conduit : Option<Utility>,
+    pub service_radius : i32,
+    // 只有 serviced (要的 utility 都有) 时交互才有效果, 由 update_utilities 每个 tick 刷新
+    pub consumes : Vec<Utility>,
+    pub serviced : bool,
+    // 起作用时每个 tick 往所在格子的场里加的量
+    pub emits : Vec<(Field, f64)>,
+    // 起作用时每个 tick 交给国库的税, 和不管起不起作用都要付的维护费
+    pub tax : i32,
+    pub upkeep : i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DrawType {
+    Round,
+    Rect,
+    Line,
+    Pixel,
+    Circle,
+    Star,
+    None,
+}
+
+impl Environment {
+    pub fn spwan(e : &Environment, pos:(i32,i32)) ->Environment {
+        Environment {
+            alive: true,
+            auto_interact : e.auto_interact,
+            tag: e.tag,
+            hp: e.hp,
+            difficulty: e.difficulty,
+            penalty: e.penalty,
+            reward: e.reward,
+            position: pos,
+            yields : e.yields,
+            color : e.color,
+            draw_type : e.draw_type.clone(),
+            d: e.d,
+            blocking : e.blocking,
+            zone : e.zone,
+            road : e.road,
+            requires_road : e.requires_road,
+            connected : false,
+            produces : e.produces,
+            conduit : e.conduit,
+            service_radius : e.service_radius,
+            consumes : e.consumes.clone(),
+            serviced : false,
+            emits : e.emits.clone(),
+            tax : e.tax,
+            upkeep : e.upkeep,
+        }
+    }
+
+    // 接上了需要的路网和 utility.
+    pub fn functioning(&self) -> bool {
+        (!self.requires_road || self.connected) && (self.consumes.is_empty() || self.serviced)
+    }
+
+    pub fn consume(&mut self, num : i32) {
+        if !self.alive {
+            return
+        }
+        self.hp -= num;
+        if self.hp <= 0 {
+            self.alive = false;
+        }
+    }
+
+    pub fn get_center_pixel_pos(&self) -> (i32, i32) {
+        (((GRID_WIDTH + 1) / 2 + GRID_WIDTH * self.position.0 as u32) as i32,
+        ((GRID_HEIGHT + 1) / 2 + GRID_HEIGHT * self.position.1 as u32) as i32)
+    }
+}
+
+pub fn is_blocked(ve : &[Environment], pos : (i32, i32)) -> bool {
+    ve.iter().any(|e| e.alive && e.blocking && e.position == pos)
+}
+
+pub fn distance(e : &Environment, a: &Animal) -> i32 {
+    i32::abs(e.position.0-a.position.0) + i32::abs(e.position.1-a.position.1)
+}
+
+// 只拿来做决策,不用来做更新,因此不需要引用
+pub fn find_environments(a:&Animal, ve:&Vec<Environment>) -> Vec<Environment> {
+    let mut vec = vec!();
+    for e in ve {
+        if distance(e, a) <= a.view_distance as i32 {
+            vec.push(e.clone());
+        }
+    }
+    vec
+}
+
+pub fn make_interaction(e : &mut Environment, a : &mut Animal, rng: &mut oorandom::Rand32) {
+    // 没接上路网或者没有 utility 服务的建筑不起作用
+    if !e.functioning() {
+        return;
+    }
+    let dif = e.difficulty;
+    let roll = rng.rand_u32() % 20;
+    let dix = roll + a.ability;
+    e.consume(1);
+    if dix >= dif {
+        a.hp += e.reward.0 as i32;
+        a.ability += e.reward.1;
+        a.inventory.add(&e.yields);
+    } else {
+        a.consume(e.penalty as i32);
+    }
+
+}
+
+// Build 的目标格子: 先是自己的格子, 再按上下左右的顺序看相邻的格子.
+const BUILD_TARGETS : [(i32, i32); 5] = [(0, 0), (1, 0), (-1, 0), (0, -1), (0, 1)];
+
+// 第一个能放下蓝图的格子: 在地图内, 没有活着的环境; 挡路的蓝图不能盖在自己脚下.
+pub fn build_target(ve: &[Environment], a: &Animal, blueprint: &EnvironmentTemplate, map_size:(i32, i32)) -> Option<(i32, i32)> {
+    BUILD_TARGETS.iter()
+        .map(|inc| (a.position.0 + inc.0, a.position.1 + inc.1))
+        .filter(|pos| pos.0 >= 0 && pos.1 >= 0 && pos.0 < map_size.0 && pos.1 < map_size.1)
+        .filter(|pos| !(blueprint.blocking && *pos == a.position))
+        .find(|pos| !ve.iter().any(|e| e.alive && e.position == *pos))
+}
+
+pub fn execute_decision(ve: &mut Vec<Environment>, a :&mut Animal, blueprints: &[EnvironmentTemplate], treasury: &mut Treasury, map_size:(i32, i32), rng :&mut oorandom::Rand32) {
+    let mut vme : Vec<&mut Environment> = vec![];
+    for e in ve.iter_mut() {
+        if e.position == a.position {
+            vme.push(e);
+        }
+    }
+    for me in vme.iter_mut() {
+        if me.auto_interact {
+            make_interaction(me, a, rng);
+        }
+    }
+    match a.next_decision {
+        Decision::MoveUp => {
+            a.move_inc_road((1, 0), map_size, ve);
+        },
+        Decision::MoveDown => {
+            a.move_inc_road((-1, 0), map_size, ve);
+        },
+        Decision::MoveLeft => {
+            a.move_inc_road((0, -1), map_size, ve);
+        },
+        Decision::MoveRight => {
+            a.move_inc_road((0, 1), map_size, ve);
+        },
+        Decision::Interact => {
+            for me in vme.iter_mut() {
+                if !me.auto_interact {
+                    make_interaction(me, a, rng);
+                }
+            }
+        },
+        // 付不起 (hp 付完会死, 资源不够, 或者国库不够) 或者没有能放的格子时什么也不做, 也不扣东西.
+        Decision::Build => {
+            if let Some(blueprint) = blueprints.get(a.blueprint as usize) {
+                let cost = blueprint.build_cost.unwrap_or(0);
+                if a.hp > cost && a.inventory.covers(&blueprint.resource_cost) && treasury.can_afford(blueprint.price) {
+                    if let Some(pos) = build_target(ve, a, blueprint, map_size) {
+                        a.consume(cost);
+                        a.inventory.take(&blueprint.resource_cost);
+                        treasury.pay(blueprint.price);
+                        ve.push(blueprint.spwan(pos));
+                    }
+                }
+            }
+        },
+        Decision::Eat if a.inventory.food > 0 => {
+            a.inventory.food -= 1;
+            a.hp += FOOD_HP;
+        },
+        // 没有食物时什么也不做
+        Decision::Eat => (),
+        Decision::CycleBlueprint => {
+            a.blueprint = (a.blueprint + 1) % u32::max(blueprints.len() as u32, 1);
+        },
+        // Decision::Wait => {},
+        _ => {},
+    }
+}
+
+// 动物之间交互的距离: 同一格或者上下左右相邻.
+pub const ANIMAL_REACH: i32 = 1;
+// 和 make_interaction 一样掷 d20 + ability, 要达到对方的 ability + ANIMAL_DIFFICULTY.
+pub const ANIMAL_DIFFICULTY: u32 = 10;
+pub const ATTACK_DAMAGE: i32 = 3;
+pub const SHARE_HP: i32 = 2;
+
+pub fn animal_distance(a : &Animal, b : &Animal) -> i32 {
+    i32::abs(a.position.0-b.position.0) + i32::abs(a.position.1-b.position.1)
+}
+
+// 视野内其它活着的动物, 不包括 va[i] 自己.
+pub fn find_animals(i : usize, va : &[Animal]) -> Vec<Animal> {
+    let a = &va[i];
+    va.iter().enumerate()
+        .filter(|(j, b)| *j != i && b.alive && animal_distance(a, b) <= a.view_distance as i32)
+        .map(|(_, b)| *b)
+        .collect()
+}
+
+// range 内离 va[i] 最近的其它活着的动物, same_species 相符的优先, 一样近的取下标小的.
+fn nearest_animal(va : &[Animal], i : usize, range : i32, same_species : bool) -> Option<usize> {
+    let a = &va[i];
+    (0..va.len())
+        .filter(|j| *j != i && va[*j].alive && animal_distance(a, &va[*j]) <= range)
+        .min_by_key(|j| ((va[*j].species == a.species) != same_species, animal_distance(a, &va[*j]), *j))
+}
+
+fn animal_roll(a : &Animal, other : &Animal, rng : &mut oorandom::Rand32) -> bool {
+    rng.rand_u32() % 20 + a.ability >= other.ability + ANIMAL_DIFFICULTY
+}
+
+// 动物对动物的决定, 在 execute_decision 之后执行:
+// Attack 打中别的物种优先的邻居, 把伤害吸收成自己的 hp, 没打中自己扣 1;
+// Flee 沿离视野内最近的别的物种最远的方向走一步, 掷骰成功走两步, 视野内只有同一物种时不动;
+// Share 分 SHARE_HP 给同一物种优先的邻居, 自己的 hp 不够就不分.
+pub fn execute_animal_decision(ve : &[Environment], va : &mut [Animal], i : usize, map_size:(i32, i32), rng :&mut oorandom::Rand32) {
+    if !va[i].alive {
+        return;
+    }
+    match va[i].next_decision {
+        Decision::Attack => {
+            if let Some(j) = nearest_animal(va, i, ANIMAL_REACH, false) {
+                if animal_roll(&va[i], &va[j], rng) {
+                    va[j].consume(ATTACK_DAMAGE);
+                    va[i].hp += ATTACK_DAMAGE;
+                } else {
+                    va[i].consume(1);
+                }
+            }
+        },
+        Decision::Flee => {
+            // 别的物种优先, 所以最近的是同一物种时视野内就没有别的物种
+            let threat = nearest_animal(va, i, va[i].view_distance as i32, false)
+                .filter(|j| va[*j].species != va[i].species);
+            if let Some(j) = threat {
+                let dx = va[i].position.0 - va[j].position.0;
+                let dy = va[i].position.1 - va[j].position.1;
+                let inc = if dx == 0 && dy == 0 {
+                    (1, 0)
+                } else if i32::abs(dx) >= i32::abs(dy) {
+                    (dx.signum(), 0)
+                } else {
+                    (0, dy.signum())
+                };
+                let steps = if animal_roll(&va[i], &va[j], rng) { 2 } else { 1 };
+                for _ in 0..steps {
+                    va[i].move_inc_blocked(inc, map_size, ve);
+                }
+            }
+        },
+        Decision::Share => {
+            if let Some(j) = nearest_animal(va, i, ANIMAL_REACH, true) {
+                if va[i].hp > SHARE_HP {
+                    va[i].consume(SHARE_HP);
+                    va[j].hp += SHARE_HP;
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+pub fn garbage_collection(ve: Vec<Environment>) -> Vec<Environment> {
+    let mut ret = vec![];
+    for e in ve {
+        if e.alive {
+            ret.push(e);
+        }
+    }
+    ret
+}
+
+// 这个 tick 开始前就在的动物按下标顺序检查, 够 hp 的在第一个相邻的空格子 (没有活着的动物, 没有被挡住) 生孩子.
+// 孩子追加到 va 末尾, 返回每个孩子的父母下标.
+pub fn reproduce(ve: &[Environment], va: &mut Vec<Animal>, rule: &Reproduction, map_size:(i32, i32)) -> Vec<usize> {
+    let mut parents = vec![];
+    for i in 0..va.len() {
+        let population = va.iter().filter(|a| a.alive).count();
+        if !va[i].alive || va[i].hp < rule.hp_threshold || population >= rule.max_population as usize {
+            continue;
+        }
+        let (x, y) = va[i].position;
+        let free = [(x + 1, y), (x - 1, y), (x, y - 1), (x, y + 1)].into_iter().find(|pos| {
+            pos.0 >= 0 && pos.1 >= 0 && pos.0 < map_size.0 && pos.1 < map_size.1
+                && !va.iter().any(|a| a.alive && a.position == *pos)
+                && !is_blocked(ve, *pos)
+        });
+        if let Some(pos) = free {
+            va[i].consume(rule.offspring_hp);
+            let mut child = Animal::spwan(&va[i], pos);
+            child.hp = rule.offspring_hp;
+            va.push(child);
+            parents.push(i);
+        }
+    }
+    parents
+}
+
+// 格子 (i, j) 掷 0..spawn_roll, 模板按顺序从上往下各占 spawn_weight 个点, 剩下的格子留空.
+// 动物的出生点上不放障碍物, 划了区的格子留给区划长建筑, 预先铺好的路上只有路;
+// 骰子照常掷, 其它格子的结果不变.
+pub fn generate_map(config : &WorldConfig) -> (Vec<Environment>,Vec<Animal>) {
+    let mut rng_initializer = oorandom::Rand32::new(config.initializer_seed);
+    let spawn_points : Vec<(i32, i32)> = config.animals.iter().flat_map(|t| t.spawn_points.clone()).collect();
+    let road_cells = config.roads.as_ref().map_or(vec![], |r| r.cells());
+    let mut ve = vec![];
+    if let Some(template) = config.roads.as_ref().and_then(|r| config.environments.iter().find(|t| t.name == r.template)) {
+        for pos in &road_cells {
+            ve.push(template.spwan(*pos));
+        }
+    }
+    for i in 1..config.width {
+        for j in 1..config.height {
+            let rng = rng_initializer.rand_u32() % config.spawn_roll;
+            if config.zoning.as_ref().is_some_and(|z| z.zone_of((i as i32, j as i32)).is_some())
+                || road_cells.contains(&(i as i32, j as i32)) {
+                continue;
+            }
+            let mut upper = config.spawn_roll;
+            for template in &config.environments {
+                let lower = upper - template.spawn_weight;
+                if rng >= lower {
+                    if !(template.blocking && spawn_points.contains(&(i as i32, j as i32))) {
+                        ve.push(template.spwan((i as i32, j as i32)));
+                    }
+                    break;
+                }
+                upper = lower;
+            }
+        }
+    }
+    let mut va = vec![];
+    for (species, template) in config.animals.iter().enumerate() {
+        for pos in &template.spawn_points {
+            let mut a = template.spwan(*pos);
+            a.species = species as u32;
+            va.push(a);
+        }
+    }
+    (ve, va)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::world::tests::blank_template;
+
+    // 测试用的最小环境: 1 hp, 不画出来, 也没有别的作用; 各个测试只改自己关心的字段.
+    pub(crate) fn blank_environment(pos : (i32, i32)) -> Environment {
+        Environment {
+            tag : EnvironmentTag::SHELTER,
+            alive : true,
+            auto_interact : false,
+            hp : 1,
+            difficulty : 0,
+            penalty : 0,
+            reward : (0, 0),
+            position : pos,
+            yields : Inventory::default(),
+            color : (0, 0, 0, 0),
+            draw_type : DrawType::None,
+            d : 0,
+            blocking : false,
+            zone : None,
+            road : false,
+            requires_road : false,
+            connected : false,
+            produces : None,
+            conduit : None,
+            service_radius : 0,
+            consumes : vec![],
+            serviced : false,
+            emits : vec![],
+            tax : 0,
+            upkeep : 0,
+        }
+    }
+
+    pub(crate) fn blank_animal(pos : (i32, i32)) -> Animal {
+        Animal {
+            alive : true,
+            hp : 10,
+            ability : 0,
+            lifetime : 0,
+            position : pos,
+            view_distance : 5,
+            next_decision : Decision::Wait,
+            species : 0,
+            blueprint : 0,
+            inventory : Inventory::default(),
+        }
+    }
+
+    #[test]
+    fn live_blocking_environments_stop_movement() {
+        let mut wall = blank_environment((1, 0));
+        wall.blocking = true;
+        let mut ve = vec![wall];
+        let mut a = blank_animal((0, 0));
+        a.move_inc_blocked((1, 0), (3, 3), &ve);
+        assert_eq!(a.position, (0, 0));
+        a.move_inc_blocked((0, 1), (3, 3), &ve);
+        assert_eq!(a.position, (0, 1));
+        a.move_inc_blocked((1, 0), (3, 3), &ve);
+        assert_eq!(a.position, (1, 1));
+        ve[0].alive = false;
+        a.move_inc_blocked((0, -1), (3, 3), &ve);
+        assert_eq!(a.position, (1, 0));
+    }
+
+    #[test]
+    fn flee_only_runs_from_other_species() {
+        let ve = vec![];
+        let mut va = vec![blank_animal((5, 5)), blank_animal((5, 6))];
+        va[0].next_decision = Decision::Flee;
+        let mut rng = oorandom::Rand32::new(1);
+        execute_animal_decision(&ve, &mut va, 0, (10, 10), &mut rng);
+        assert_eq!(va[0].position, (5, 5));
+        va[1].species = 1;
+        execute_animal_decision(&ve, &mut va, 0, (10, 10), &mut rng);
+        assert_eq!(va[0].position.0, 5);
+        assert!(va[0].position.1 < 5);
+    }
+
+    #[test]
+    fn build_takes_resources_only_when_the_inventory_covers_them() {
+        let mut hut = blank_template("hut");
+        hut.build_cost = Some(2);
+        hut.resource_cost = Inventory { food : 0, wood : 3, stone : 1 };
+        let blueprints = vec![hut];
+        let mut treasury = Treasury::new(&None);
+        let mut rng = oorandom::Rand32::new(1);
+        let mut ve = vec![];
+        let mut a = blank_animal((1, 1));
+        a.next_decision = Decision::Build;
+        a.inventory = Inventory { food : 5, wood : 2, stone : 1 };
+        assert!(!a.inventory.covers(&blueprints[0].resource_cost));
+        execute_decision(&mut ve, &mut a, &blueprints, &mut treasury, (3, 3), &mut rng);
+        assert!(ve.is_empty());
+        assert_eq!(a.hp, 10);
+        assert_eq!(a.inventory, Inventory { food : 5, wood : 2, stone : 1 });
+        a.inventory.wood = 4;
+        assert!(a.inventory.covers(&blueprints[0].resource_cost));
+        execute_decision(&mut ve, &mut a, &blueprints, &mut treasury, (3, 3), &mut rng);
+        assert_eq!(ve.len(), 1);
+        assert_eq!(ve[0].position, (1, 1));
+        assert_eq!(a.hp, 8);
+        assert_eq!(a.inventory, Inventory { food : 5, wood : 1, stone : 0 });
+    }
+}
@@ -0,0 +1,571 @@
+#[cfg(feature = "gui")]
+use winit::{
+    event::{Event, VirtualKeyCode},
+    event_loop::ControlFlow,
+};
+#[cfg(feature = "gui")]
+use winit_input_helper::WinitInputHelper;
+
+#[cfg(feature = "gui")]
+use crate::abstraction::StateAbstraction;
+#[cfg(feature = "gui")]
+use crate::imitation::HumanTrace;
+use crate::policy::{Decision, Observation, Outcome, Policy, PolicyError};
+#[cfg(feature = "gui")]
+use crate::rendering::{build_window, clear_pixels, visualize_heatmap, visualize_map};
+use crate::budget::{settle_budget, Treasury};
+use crate::fields::{update_fields, ScalarFields};
+use crate::world::{EnvironmentTemplate, WorldConfig};
+use crate::roads::update_road_access;
+use crate::utilities::update_utilities;
+use crate::zoning::grow_zones;
+use crate::simulation::{
+    Animal, Environment, Inventory, Tickable,
+    execute_animal_decision, execute_decision, find_animals, find_environments, garbage_collection,
+    generate_map, reproduce,
+};
+
+// 一局里随着 tick 变化的世界: 环境, 动物, 场, 国库和当前的 tick.
+pub struct Episode {
+    pub ve : Vec<Environment>,
+    pub va : Vec<Animal>,
+    pub fields : ScalarFields,
+    pub treasury : Treasury,
+    pub tick : u128,
+    // 世界配置里的蓝图一局内不变, 开局时算一次
+    pub blueprints : Vec<EnvironmentTemplate>,
+}
+
+impl Episode {
+    pub fn new(world: &WorldConfig) -> Self {
+        let (ve, va) = generate_map(world);
+        Episode {
+            ve,
+            va,
+            fields : ScalarFields::new(world.map_size()),
+            treasury : Treasury::new(&world.budget),
+            tick : 0,
+            blueprints : world.blueprints(),
+        }
+    }
+
+    // va[i] 做决定时看到的世界.
+    pub fn observe(&self, i: usize, world: &WorldConfig) -> Observation {
+        let a = &self.va[i];
+        Observation {
+            agent : i,
+            environments : find_environments(a, &self.ve),
+            animals : find_animals(i, &self.va),
+            fields : self.fields.levels(a.position, &world.fields),
+            treasury : self.treasury.level(&world.budget),
+        }
+    }
+
+    // 动物都 tick 过之后: 进到下一个 tick, 清理环境, 长建筑, 更新场, 结算预算.
+    fn finish_tick(&mut self, world: &WorldConfig, rng_world: &mut oorandom::Rand32) {
+        self.tick += 1;
+        self.ve = garbage_collection(std::mem::take(&mut self.ve));
+        grow_zones(&mut self.ve, world, self.tick, rng_world);
+        update_fields(&mut self.fields, &self.ve, &world.fields);
+        settle_budget(&mut self.treasury, &self.ve, &world.budget);
+    }
+}
+
+// 世界的随机数(交互掷骰)和决策的随机数分开, 这样记录下来的决策可以被原样重放.
+fn decision_making_step(
+    episode: &mut Episode,
+    world: &WorldConfig,
+    rng_world: &mut oorandom::Rand32,
+) {
+    let map_size = world.map_size();
+    update_road_access(&mut episode.ve, map_size);
+    update_utilities(&mut episode.ve);
+    execute_decision(&mut episode.ve, &mut episode.va[0], &episode.blueprints, &mut episode.treasury, map_size, rng_world);
+    execute_animal_decision(&episode.ve, &mut episode.va, 0, map_size, rng_world);
+    for a in episode.va.iter_mut() {
+        a.tick();
+    }
+    episode.finish_tick(world, rng_world);
+}
+
+// va[0] 观察, 做决定, 执行, 然后把这个 tick 的结果交给策略学习.
+fn decision_making_policy_step<P: Policy>(
+    policy: &mut P,
+    episode: &mut Episode,
+    world: &WorldConfig,
+    rng_policy: &mut oorandom::Rand32,
+    rng_world: &mut oorandom::Rand32,
+) {
+    let observation = episode.observe(0, world);
+    episode.va[0].next_decision = policy.make_a_decision(episode.tick, &episode.va[0], observation, rng_policy);
+    let before = episode.va[0];
+    let decided_tick = episode.tick;
+    decision_making_step(episode, world, rng_world);
+    policy.learn(&Outcome::between(decided_tick, 0, &before, &episode.va[0]));
+}
+
+pub fn decision_making_single_loop<P: Policy + 'static>(
+    _show_visuals: bool,
+    mut _policy: P,
+    _world_config: &WorldConfig,
+) -> (P, u128) {
+    let calculator_seed = 64;
+    let policy_seed = 64;
+    let mut rng_calculator = oorandom::Rand32::new(calculator_seed);
+    let mut rng_policy = oorandom::Rand32::new(policy_seed);
+    let world = _world_config.clone();
+    let mut episode = Episode::new(&world);
+    // headless 构建时没有窗口, 只剩下非可视化的分支.
+    #[cfg(feature = "gui")]
+    if _show_visuals {
+        let (event_loop, window, mut pixels) = build_window();
+        event_loop.run(move |_, _, control_flow| {
+            if !episode.va[0].alive {
+                println!("Player Dead in tick {:?}", episode.tick);
+                _policy.end_episode();
+                *control_flow = ControlFlow::Exit;
+            } else if episode.treasury.bankrupt {
+                println!("City Bankrupt in tick {:?}", episode.tick);
+                _policy.end_episode();
+                *control_flow = ControlFlow::Exit;
+            } else {
+                // 剩下的loop操作也在这里写.
+                clear_pixels(pixels.get_frame_mut());
+                visualize_heatmap(&episode.fields, pixels.get_frame_mut());
+                visualize_map(&episode.ve, &episode.va, pixels.get_frame_mut());
+                pixels.render().unwrap();
+                decision_making_policy_step(&mut _policy, &mut episode, &world, &mut rng_policy, &mut rng_calculator);
+                window.request_redraw();
+            }
+        });
+    }
+    // 玩家死掉或者城市破产时一局结束.
+    while episode.va[0].alive && !episode.treasury.bankrupt {
+        decision_making_policy_step(&mut _policy, &mut episode, &world, &mut rng_policy, &mut rng_calculator);
+        if !episode.va[0].alive {
+            println!("Player Dead in tick {:?}", episode.tick);
+        } else if episode.treasury.bankrupt {
+            println!("City Bankrupt in tick {:?}", episode.tick);
+        }
+    }
+    _policy.end_episode();
+    (_policy, episode.tick)
+}
+
+// 屏幕上 position.0 是行, 往下增大, 所以 MoveUp 在屏幕上是往下走;
+// 方向键按屏幕上看到的方向映射.
+#[cfg(feature = "gui")]
+fn decision_for_key(input: &WinitInputHelper) -> Option<Decision> {
+    const KEYS : [(VirtualKeyCode, Decision); 12] = [
+        (VirtualKeyCode::Up, Decision::MoveDown),
+        (VirtualKeyCode::Down, Decision::MoveUp),
+        (VirtualKeyCode::Left, Decision::MoveLeft),
+        (VirtualKeyCode::Right, Decision::MoveRight),
+        (VirtualKeyCode::I, Decision::Interact),
+        (VirtualKeyCode::B, Decision::Build),
+        (VirtualKeyCode::Space, Decision::Wait),
+        (VirtualKeyCode::A, Decision::Attack),
+        (VirtualKeyCode::F, Decision::Flee),
+        (VirtualKeyCode::S, Decision::Share),
+        (VirtualKeyCode::Tab, Decision::CycleBlueprint),
+        (VirtualKeyCode::E, Decision::Eat),
+    ];
+    KEYS.iter().find(|(key, _)| input.key_pressed(*key)).map(|(_, d)| *d)
+}
+
+// 人来玩: 每按一次键 va[0] 执行对应的决定并前进一个 tick, 用来试玩地图的难度.
+// 给了 _to_json 时把这一局接在记录文件后面, 退出或死亡时保存, 之后可以拿来做行为克隆.
+#[cfg(feature = "gui")]
+pub fn decision_making_play(
+    _world_config: &WorldConfig,
+    _abstraction: StateAbstraction,
+    _to_json: Option<String>,
+) -> Result<(), PolicyError> {
+    let mut trace = match &_to_json {
+        Some(json_path) => Some(HumanTrace::resume(json_path, _abstraction)?),
+        None => None,
+    };
+    let calculator_seed = 64;
+    let mut rng_calculator = oorandom::Rand32::new(calculator_seed);
+    let world = _world_config.clone();
+    let mut episode = Episode::new(&world);
+    let mut input = WinitInputHelper::new();
+    let (event_loop, window, mut pixels) = build_window();
+    event_loop.run(move |event, _, control_flow| {
+        if let Event::RedrawRequested(_) = event {
+            clear_pixels(pixels.get_frame_mut());
+            visualize_heatmap(&episode.fields, pixels.get_frame_mut());
+            visualize_map(&episode.ve, &episode.va, pixels.get_frame_mut());
+            pixels.render().unwrap();
+        }
+        if !input.update(&event) {
+            return;
+        }
+        let quit = input.quit() || input.key_pressed(VirtualKeyCode::Escape);
+        if quit {
+            println!("Player quit in tick {:?}", episode.tick);
+        } else if let Some(decision) = decision_for_key(&input) {
+            if let Some(trace) = trace.as_mut() {
+                trace.record(episode.tick, &episode.va[0], episode.observe(0, &world), decision);
+            }
+            episode.va[0].next_decision = decision;
+            decision_making_step(&mut episode, &world, &mut rng_calculator);
+            if !episode.va[0].alive {
+                println!("Player Dead in tick {:?}", episode.tick);
+            } else if episode.treasury.bankrupt {
+                println!("City Bankrupt in tick {:?}", episode.tick);
+            }
+            window.request_redraw();
+        }
+        if quit || !episode.va[0].alive || episode.treasury.bankrupt {
+            if let (Some(trace), Some(json_path)) = (&trace, &_to_json) {
+                match trace.to_json(json_path.clone()) {
+                    Ok(()) => println!("RECORDED {} EPISODES", trace.episode_count()),
+                    Err(err) => println!("{}", err),
+                }
+            }
+            *control_flow = ControlFlow::Exit;
+        }
+    });
+}
+
+// 多个动物的策略: 所有动物共用一个, 或者每个动物 (按 va 的下标) 各用一个.
+// 共用时所有动物的决定交错记在同一份历史里, 待学习的记录按动物的下标分开.
+#[derive(Clone, Debug)]
+pub enum Agents<P> {
+    Shared(P),
+    PerAnimal(Vec<P>),
+}
+
+impl<P: Policy> Agents<P> {
+    pub fn policy_mut(&mut self, index: usize) -> &mut P {
+        match self {
+            Agents::Shared(policy) => policy,
+            Agents::PerAnimal(policies) => &mut policies[index],
+        }
+    }
+
+    // 新生的孩子拿到父母策略变异后的一份, 不带父母的历史; 共用策略时什么也不做.
+    fn adopt(&mut self, parent: usize, _mutate_factor: u32, rng: &mut oorandom::Rand32) {
+        if let Agents::PerAnimal(policies) = self {
+            let mut child = policies[parent].mutate(_mutate_factor, rng);
+            child.clear_history();
+            policies.push(child);
+        }
+    }
+    // 要保存的那一份: 共用的策略, 或者活到最后 (都死了就是活得最久) 的动物的策略, 一样时取下标小的.
+    fn into_best(self, result: &EpisodeResult) -> P {
+        match self {
+            Agents::Shared(policy) => policy,
+            Agents::PerAnimal(mut policies) => {
+                let best = result.animals.iter().enumerate()
+                    .max_by_key(|(i, a)| (a.alive, a.lifetime, std::cmp::Reverse(*i)))
+                    .map_or(0, |(i, _)| i);
+                policies.swap_remove(best)
+            },
+        }
+    }
+
+    // 共用的策略一局只结束一次, 不是每只动物死的时候各一次.
+    fn end_episode(&mut self) {
+        match self {
+            Agents::Shared(policy) => policy.end_episode(),
+            Agents::PerAnimal(policies) => policies.iter_mut().for_each(|p| p.end_episode()),
+        }
+    }
+}
+
+// 一个动物在一局结束时的情况.
+#[derive(Clone, Copy, Debug)]
+pub struct AnimalResult {
+    // 一开始就在的动物没有父母
+    pub parent : Option<usize>,
+    pub alive : bool,
+    pub lifetime : u32,
+    pub hp : i32,
+    pub ability : u32,
+    pub inventory : Inventory,
+}
+
+#[derive(Clone, Debug)]
+pub struct EpisodeResult {
+    pub ticks : u128,
+    pub animals : Vec<AnimalResult>,
+    // 世界配置里没有 budget 时是 None
+    pub treasury : Option<i32>,
+    pub bankrupt : bool,
+}
+
+impl EpisodeResult {
+    fn from_episode(episode : &Episode, parents : &[Option<usize>]) -> Self {
+        let treasury = &episode.treasury;
+        EpisodeResult {
+            ticks : episode.tick,
+            treasury : if treasury.enabled { Some(treasury.balance) } else { None },
+            bankrupt : treasury.bankrupt,
+            animals : episode.va.iter().zip(parents).map(|(a, parent)| AnimalResult {
+                parent : *parent,
+                alive : a.alive,
+                lifetime : a.lifetime,
+                hp : a.hp,
+                ability : a.ability,
+                inventory : a.inventory,
+            }).collect(),
+        }
+    }
+}
+
+// 所有活着的动物按 va 的下标顺序: 先都观察同一个世界并做决定, 再依次执行,
+// 然后一起 tick, 各自把结果交给自己的策略, 最后够 hp 的动物繁殖.
+fn decision_making_agents_step<P: Policy>(
+    agents: &mut Agents<P>,
+    episode: &mut Episode,
+    parents: &mut Vec<Option<usize>>,
+    world: &WorldConfig,
+    rng_policy: &mut oorandom::Rand32,
+    rng_world: &mut oorandom::Rand32,
+) {
+    let map_size = world.map_size();
+    update_road_access(&mut episode.ve, map_size);
+    update_utilities(&mut episode.ve);
+    let acting : Vec<usize> = (0..episode.va.len()).filter(|i| episode.va[*i].alive).collect();
+    for i in &acting {
+        let observation = episode.observe(*i, world);
+        episode.va[*i].next_decision = agents.policy_mut(*i).make_a_decision(episode.tick, &episode.va[*i], observation, rng_policy);
+    }
+    let before = episode.va.clone();
+    for i in &acting {
+        // 可能在这个 tick 里先被别的动物杀死了
+        if !episode.va[*i].alive {
+            continue;
+        }
+        execute_decision(&mut episode.ve, &mut episode.va[*i], &episode.blueprints, &mut episode.treasury, map_size, rng_world);
+        execute_animal_decision(&episode.ve, &mut episode.va, *i, map_size, rng_world);
+    }
+    for a in episode.va.iter_mut() {
+        a.tick();
+    }
+    for i in &acting {
+        agents.policy_mut(*i).learn(&Outcome::between(episode.tick, *i, &before[*i], &episode.va[*i]));
+    }
+    if let Some(rule) = &world.reproduction {
+        for parent in reproduce(&episode.ve, &mut episode.va, rule, map_size) {
+            agents.adopt(parent, rule.mutate_factor, rng_policy);
+            parents.push(Some(parent));
+        }
+    }
+    episode.finish_tick(world, rng_world);
+}
+
+// 一局在所有动物都死掉, 城市破产或者到达 _tick_limit 时结束.
+pub fn decision_making_agents_loop<P: Policy + 'static>(
+    _show_visuals: bool,
+    mut _agents: Agents<P>,
+    _world_config: &WorldConfig,
+    _tick_limit: Option<u128>,
+) -> (Agents<P>, EpisodeResult) {
+    let calculator_seed = 64;
+    let policy_seed = 64;
+    let mut rng_calculator = oorandom::Rand32::new(calculator_seed);
+    let mut rng_policy = oorandom::Rand32::new(policy_seed);
+    let world = _world_config.clone();
+    let mut episode = Episode::new(&world);
+    if let Agents::PerAnimal(policies) = &_agents {
+        assert_eq!(policies.len(), episode.va.len(), "one policy per spawned animal");
+    }
+    let mut parents = vec![None; episode.va.len()];
+    let running = move |episode: &Episode| {
+        episode.va.iter().any(|a| a.alive) && !episode.treasury.bankrupt && _tick_limit.map_or(true, |limit| episode.tick < limit)
+    };
+    #[cfg(feature = "gui")]
+    if _show_visuals {
+        let (event_loop, window, mut pixels) = build_window();
+        event_loop.run(move |_, _, control_flow| {
+            if !running(&episode) {
+                print_episode_result(&EpisodeResult::from_episode(&episode, &parents));
+                _agents.end_episode();
+                *control_flow = ControlFlow::Exit;
+            } else {
+                clear_pixels(pixels.get_frame_mut());
+                visualize_heatmap(&episode.fields, pixels.get_frame_mut());
+                visualize_map(&episode.ve, &episode.va, pixels.get_frame_mut());
+                pixels.render().unwrap();
+                decision_making_agents_step(&mut _agents, &mut episode, &mut parents, &world, &mut rng_policy, &mut rng_calculator);
+                window.request_redraw();
+            }
+        });
+    }
+    while running(&episode) {
+        decision_making_agents_step(&mut _agents, &mut episode, &mut parents, &world, &mut rng_policy, &mut rng_calculator);
+    }
+    _agents.end_episode();
+    let result = EpisodeResult::from_episode(&episode, &parents);
+    print_episode_result(&result);
+    (_agents, result)
+}
+
+pub fn print_episode_result(result: &EpisodeResult) {
+    println!("EPISODE FINISHED IN TICK {:?}", result.ticks);
+    if let Some(balance) = result.treasury {
+        println!("TREASURY {}{}", balance, if result.bankrupt { " BANKRUPT" } else { "" });
+    }
+    for (i, a) in result.animals.iter().enumerate() {
+        let parent = match a.parent {
+            Some(parent) => format!(" CHILD OF {}", parent),
+            None => String::new(),
+        };
+        println!("ANIMAL {}{} {} LIFETIME {} HP {} ABILITY {} FOOD {} WOOD {} STONE {}",
+            i, parent, if a.alive { "ALIVE" } else { "DEAD" }, a.lifetime, a.hp, a.ability,
+            a.inventory.food, a.inventory.wood, a.inventory.stone);
+    }
+}
+
+// 所有出生点上的动物一起跑一局, 共用或者各自复制一份策略.
+// 给了 _to_json 时保存这一局之后的策略, 各自一份时保存表现最好的动物的那份.
+pub fn decision_making_simulate<P: Policy + 'static>(
+    _show_visuals: bool,
+    _world_config: &WorldConfig,
+    _policy: P,
+    _shared: bool,
+    _tick_limit: Option<u128>,
+    _to_json: Option<String>,
+) -> Result<EpisodeResult, PolicyError> {
+    let mut policy = _policy;
+    policy.clear_history();
+    let agents = if _shared {
+        Agents::Shared(policy)
+    } else {
+        let animal_count = _world_config.animals.iter().map(|t| t.spawn_points.len()).sum();
+        Agents::PerAnimal(vec![policy; animal_count])
+    };
+    let (agents, result) = decision_making_agents_loop(_show_visuals, agents, _world_config, _tick_limit);
+    if let Some(json_path) = _to_json {
+        agents.into_best(&result).to_json(json_path)?;
+    }
+    Ok(result)
+}
+
+// 只重放最后一局: decision_history 在训练中跨局累积, tick 归零处即为一局的开始.
+fn last_episode<P: Policy>(_policy: &P) -> Vec<Decision> {
+    let history = _policy.decision_history();
+    let start = history.iter().rposition(|(tick, _, _)| *tick == 0).unwrap_or(0);
+    history[start..].iter().map(|(_, _, d)| *d).collect()
+}
+
+pub fn decision_making_replay<P: Policy>(
+    _show_visuals: bool,
+    _policy: &P,
+    _world_config: &WorldConfig,
+) -> u128 {
+    let calculator_seed = 64;
+    let mut rng_calculator = oorandom::Rand32::new(calculator_seed);
+    let world = _world_config.clone();
+    let mut episode = Episode::new(&world);
+    let decisions = last_episode(_policy);
+    #[cfg(feature = "gui")]
+    if _show_visuals {
+        let (event_loop, window, mut pixels) = build_window();
+        event_loop.run(move |_, _, control_flow| {
+            if !episode.va[0].alive || episode.treasury.bankrupt || episode.tick as usize >= decisions.len() {
+                println!("Replay finished in tick {:?}", episode.tick);
+                *control_flow = ControlFlow::Exit;
+            } else {
+                clear_pixels(pixels.get_frame_mut());
+                visualize_heatmap(&episode.fields, pixels.get_frame_mut());
+                visualize_map(&episode.ve, &episode.va, pixels.get_frame_mut());
+                pixels.render().unwrap();
+                episode.va[0].next_decision = decisions[episode.tick as usize];
+                decision_making_step(&mut episode, &world, &mut rng_calculator);
+                window.request_redraw();
+            }
+        });
+    }
+    while episode.va[0].alive && !episode.treasury.bankrupt && (episode.tick as usize) < decisions.len() {
+        episode.va[0].next_decision = decisions[episode.tick as usize];
+        decision_making_step(&mut episode, &world, &mut rng_calculator);
+    }
+    println!("Replay finished in tick {:?}", episode.tick);
+    episode.tick
+}
+
+pub fn decision_making_evaluate<P: Policy + 'static>(
+    _world_config: &WorldConfig,
+    _policy: P,
+    _to_json:Option<String>) -> Result<u128, PolicyError> {
+    let mut policy = _policy;
+    policy.clear_history();
+    let (policy, tick) = decision_making_single_loop(false, policy, _world_config);
+    println!("EVALUATION SURVIVED {:?}", tick);
+    if let Some(json_path) = _to_json {
+        policy.to_json(json_path)?;
+    }
+    Ok(tick)
+}
+
+// 训练时读档失败不中断整个训练, 汇报错误后从 _initial_policy 开始.
+// 读到的决策树保留它自己的 abstraction, 否则已有的 key 就对不上了.
+pub fn resume_policy<P: Policy>(_from_json:Option<String>, _initial_policy: P) -> P {
+    match _from_json.map(P::from_json) {
+        Some(Ok(policy)) => policy,
+        Some(Err(err)) => {
+            println!("{}, training from an empty policy", err);
+            _initial_policy
+        },
+        None => _initial_policy,
+    }
+}
+
+// 探索程度从 from 线性退火到 to, 第一个 run 是 from, 最后一个 run 是 to.
+fn annealed_exploration(_exploration:Option<(f64, f64)>, run:u32, run_count:u32) -> Option<f64> {
+    let (from, to) = _exploration?;
+    if run_count <= 1 {
+        return Some(from);
+    }
+    Some(from + (to - from) * run as f64 / (run_count - 1) as f64)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrainingConfig {
+    pub run_count : u32,
+    pub mutate_factor : u32,
+    pub reward_factor : u32,
+    // 每个 run 变异出的样本数, 活得最久的那个得到奖励
+    pub sample_count : u32,
+    // 探索程度从 from 退火到 to, None 时保留策略自己的设置
+    pub exploration : Option<(f64, f64)>,
+}
+
+pub fn decision_making_run<P: Policy + 'static>(
+    _show_visuals: bool,
+    _config: TrainingConfig,
+    _world_config: &WorldConfig,
+    _policy: P,
+    _to_json:Option<String>) -> Result<(), PolicyError> {
+    let mutator_seed = 64;
+    let mut rng_mutator = oorandom::Rand32::new(mutator_seed);
+    let mut policy = _policy;
+    for run in 0.._config.run_count {
+        println!("RUNNING COUNT {:?}", run);
+        if let Some(exploration) = annealed_exploration(_config.exploration, run, _config.run_count) {
+            policy.set_exploration(exploration);
+        }
+        let mut result_vec = vec![];
+        for sample in 0.._config.sample_count {
+            println!("SAMPLE COUNT {:?}", sample);
+            let policy_sample = policy.mutate(_config.mutate_factor, &mut rng_mutator);
+            result_vec.push(decision_making_single_loop(_show_visuals, policy_sample, _world_config));
+        }
+        let (mut rpolicy, mut max_tick) = (policy.clone(), 0);
+        for (sample, tick) in result_vec {
+            if tick > max_tick {
+                rpolicy = sample;
+                max_tick = tick;
+            }
+        }
+        policy = rpolicy.reward(_config.reward_factor);
+    }
+    match _to_json {
+        Some(json_path) => policy.to_json(json_path),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, VecDeque};
+use serde_derive::{Serialize,Deserialize};
+
+use crate::roads::NEIGHBOURS;
+use crate::simulation::Environment;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Utility {
+    Power,
+    Water,
+}
+
+pub const UTILITIES : [Utility; 2] = [Utility::Power, Utility::Water];
+
+// 没有服务的消费者每个 tick 掉的 hp.
+pub const UNSERVICED_DECAY : i32 = 1;
+
+// 供应格子到它的服务半径.
+pub type ServiceArea = HashMap<(i32, i32), i32>;
+
+// 从产出 utility 的建筑出发, 沿着上下左右相连的同种管线能到的格子, 以及每个格子的服务半径.
+pub fn supplied_cells(ve : &[Environment], utility : Utility) -> ServiceArea {
+    let mut conduits : HashMap<(i32, i32), i32> = HashMap::new();
+    for e in ve.iter().filter(|e| e.alive && e.conduit == Some(utility)) {
+        let radius = conduits.entry(e.position).or_insert(e.service_radius);
+        *radius = i32::max(*radius, e.service_radius);
+    }
+    let mut supplied : ServiceArea = HashMap::new();
+    let mut queue = VecDeque::new();
+    for e in ve.iter().filter(|e| e.alive && e.produces == Some(utility)) {
+        let radius = supplied.entry(e.position).or_insert(e.service_radius);
+        *radius = i32::max(*radius, e.service_radius);
+        queue.push_back(e.position);
+    }
+    while let Some(pos) = queue.pop_front() {
+        for inc in NEIGHBOURS {
+            let next = (pos.0 + inc.0, pos.1 + inc.1);
+            if let (Some(radius), false) = (conduits.get(&next), supplied.contains_key(&next)) {
+                supplied.insert(next, *radius);
+                queue.push_back(next);
+            }
+        }
+    }
+    supplied
+}
+
+fn within_reach(supplied : &ServiceArea, pos : (i32, i32)) -> bool {
+    supplied.iter().any(|(p, radius)| i32::abs(p.0 - pos.0) + i32::abs(p.1 - pos.1) <= *radius)
+}
+
+// 每个 tick 在决定之前刷新消费者有没有服务: 要的每种 utility 都在某个供应格子的服务半径内.
+// 没有服务的消费者交互不起作用, 并且掉 UNSERVICED_DECAY 的 hp.
+pub fn update_utilities(ve : &mut [Environment]) {
+    if !ve.iter().any(|e| !e.consumes.is_empty()) {
+        return;
+    }
+    let supplied : Vec<(Utility, ServiceArea)> = UTILITIES.iter().map(|u| (*u, supplied_cells(ve, *u))).collect();
+    for e in ve.iter_mut().filter(|e| e.alive && !e.consumes.is_empty()) {
+        e.serviced = e.consumes.iter().all(|u| {
+            supplied.iter().any(|(s, cells)| s == u && within_reach(cells, e.position))
+        });
+        if !e.serviced {
+            e.consume(UNSERVICED_DECAY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::blank_environment;
+
+    fn building(pos : (i32, i32), produces : Option<Utility>, conduit : Option<Utility>, radius : i32) -> Environment {
+        let mut e = blank_environment(pos);
+        e.produces = produces;
+        e.conduit = conduit;
+        e.service_radius = radius;
+        e
+    }
+
+    #[test]
+    fn supply_follows_connected_conduits_of_the_same_utility() {
+        let ve = vec![
+            building((0, 0), Some(Utility::Power), None, 1),
+            building((1, 0), None, Some(Utility::Power), 2),
+            building((2, 0), None, Some(Utility::Power), 2),
+            building((3, 0), None, Some(Utility::Water), 2),
+            building((5, 5), None, Some(Utility::Power), 2),
+        ];
+        let supplied = supplied_cells(&ve, Utility::Power);
+        assert_eq!(supplied.len(), 3);
+        assert_eq!(supplied[&(0, 0)], 1);
+        assert_eq!(supplied[&(2, 0)], 2);
+        assert!(within_reach(&supplied, (4, 0)));
+        assert!(!within_reach(&supplied, (5, 5)));
+        assert!(supplied_cells(&ve, Utility::Water).is_empty());
+    }
+
+    #[test]
+    fn dead_producers_supply_nothing() {
+        let mut ve = vec![
+            building((0, 0), Some(Utility::Water), None, 1),
+            building((1, 0), None, Some(Utility::Water), 1),
+        ];
+        ve[0].alive = false;
+        assert!(supplied_cells(&ve, Utility::Water).is_empty());
+    }
+}
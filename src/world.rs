@@ -0,0 +1,381 @@
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use serde_derive::{Serialize,Deserialize};
+
+use crate::budget::BudgetConfig;
+use crate::fields::{Field, FieldConfig};
+use crate::policy::Decision;
+use crate::simulation::{Animal, Environment, EnvironmentTag, DrawType, Inventory, WIDTH, HEIGHT};
+use crate::roads::RoadConfig;
+use crate::utilities::Utility;
+use crate::zoning::ZoningConfig;
+
+// 一种环境的模板, spawn_weight / spawn_roll 即每个格子生成它的概率.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnvironmentTemplate {
+    pub name : String,
+    pub spawn_weight : u32,
+    pub tag : EnvironmentTag,
+    pub auto_interact : bool,
+    pub hp : i32,
+    pub difficulty : u32,
+    pub penalty : u32,
+    pub reward : (u32, u32),
+    #[serde(default)]
+    pub yields : Inventory,
+    pub color : (u8, u8, u8, u8),
+    pub draw_type : DrawType,
+    pub d : u32,
+    #[serde(default)]
+    pub blocking : bool,
+    #[serde(default)]
+    pub road : bool,
+    #[serde(default)]
+    pub requires_road : bool,
+    #[serde(default)]
+    pub produces : Option<Utility>,
+    #[serde(default)]
+    pub conduit : Option<Utility>,
+    #[serde(default)]
+    pub service_radius : i32,
+    #[serde(default)]
+    pub consumes : Vec<Utility>,
+    #[serde(default)]
+    pub emits : Vec<(Field, f64)>,
+    #[serde(default)]
+    pub tax : i32,
+    #[serde(default)]
+    pub upkeep : i32,
+    // Build 时从国库里扣的钱
+    #[serde(default)]
+    pub price : i32,
+    // 有这一项的模板也是 Build 用的蓝图, 建造时扣这么多 hp
+    #[serde(default)]
+    pub build_cost : Option<i32>,
+    // 建造时还要从背包里扣的资源
+    #[serde(default)]
+    pub resource_cost : Inventory,
+}
+
+impl EnvironmentTemplate {
+    pub fn spwan(&self, pos:(i32,i32)) -> Environment {
+        Environment {
+            alive: true,
+            auto_interact : self.auto_interact,
+            tag: self.tag,
+            hp: self.hp,
+            difficulty: self.difficulty,
+            penalty: self.penalty,
+            reward: self.reward,
+            position: pos,
+            yields : self.yields,
+            color : self.color,
+            draw_type : self.draw_type.clone(),
+            d: self.d,
+            blocking : self.blocking,
+            zone : None,
+            road : self.road,
+            requires_road : self.requires_road,
+            connected : false,
+            produces : self.produces,
+            conduit : self.conduit,
+            service_radius : self.service_radius,
+            consumes : self.consumes.clone(),
+            serviced : false,
+            emits : self.emits.clone(),
+            tax : self.tax,
+            upkeep : self.upkeep,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnimalTemplate {
+    pub name : String,
+    pub hp : i32,
+    pub ability : u32,
+    pub view_distance : u32,
+    pub spawn_points : Vec<(i32, i32)>,
+    // 出生时背包里的资源
+    #[serde(default)]
+    pub inventory : Inventory,
+}
+
+impl AnimalTemplate {
+    pub fn spwan(&self, pos:(i32,i32)) -> Animal {
+        Animal {
+            alive : true,
+            hp : self.hp,
+            ability : self.ability,
+            lifetime : 0,
+            position : pos,
+            view_distance: self.view_distance,
+            next_decision : Decision::Wait,
+            species : 0,
+            blueprint : 0,
+            inventory : self.inventory,
+        }
+    }
+}
+
+// hp 达到 hp_threshold 的动物在相邻的空格子生一个孩子, 分给它 offspring_hp 点 hp.
+// 孩子继承父母策略按 mutate_factor 变异后的一份; 活着的动物最多 max_population 个.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Reproduction {
+    pub hp_threshold : i32,
+    pub offspring_hp : i32,
+    pub mutate_factor : u32,
+    pub max_population : u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorldConfig {
+    pub width : u32,
+    pub height : u32,
+    pub initializer_seed : u64,
+    pub spawn_roll : u32,
+    pub environments : Vec<EnvironmentTemplate>,
+    pub animals : Vec<AnimalTemplate>,
+    // 没有这一项时动物不繁殖.
+    #[serde(default)]
+    pub reproduction : Option<Reproduction>,
+    // 没有这一项时不划区, 也不长建筑.
+    #[serde(default)]
+    pub zoning : Option<ZoningConfig>,
+    // 预先铺好的路, 没有这一项时地图上一开始没有路.
+    #[serde(default)]
+    pub roads : Option<RoadConfig>,
+    // 污染, 地价, 犯罪的扩散和衰减, 没有这一项时不模拟这些场.
+    #[serde(default)]
+    pub fields : Option<FieldConfig>,
+    // 国库, 税收和破产, 没有这一项时建筑不收税也不花钱.
+    #[serde(default)]
+    pub budget : Option<BudgetConfig>,
+}
+
+#[derive(Debug)]
+pub enum WorldConfigError {
+    Io(String, std::io::Error),
+    Malformed(String, serde_json::Error),
+    MapSize(u32, u32),
+    SpawnRoll(u32, u32),
+    DuplicateName(String),
+    Template(String, &'static str),
+    NoAnimals,
+    SpawnPoint(String, (i32, i32)),
+    Reproduction(&'static str),
+    Zoning(String),
+    Roads(String),
+    Fields(&'static str),
+    Budget(&'static str),
+}
+
+impl fmt::Display for WorldConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use WorldConfigError::*;
+        match self {
+            Io(path, err) => write!(f, "cannot read world config {}: {}", path, err),
+            Malformed(path, err) => write!(f, "malformed world config {}: {}", path, err),
+            MapSize(width, height) => write!(f,
+                "map size {}x{} must be between 1x1 and {}x{}", width, height, WIDTH, HEIGHT),
+            SpawnRoll(total, roll) => write!(f,
+                "spawn weights add up to {} but spawn_roll is {}; spawn_roll must be positive and at least the sum", total, roll),
+            DuplicateName(name) => write!(f, "template name {:?} is used more than once", name),
+            Template(name, msg) => write!(f, "template {:?}: {}", name, msg),
+            NoAnimals => write!(f, "world config declares no animal spawn points"),
+            SpawnPoint(name, pos) => write!(f, "animal {:?} spawn point {:?} is outside the map", name, pos),
+            Reproduction(msg) => write!(f, "reproduction: {}", msg),
+            Zoning(msg) => write!(f, "zoning: {}", msg),
+            Roads(msg) => write!(f, "roads: {}", msg),
+            Fields(msg) => write!(f, "fields: {}", msg),
+            Budget(msg) => write!(f, "budget: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for WorldConfigError {}
+
+// 内置的默认世界就是仓库里的 world_config.json, 编译时嵌进来, 只维护这一份.
+// 与原先写死在 generate_map 中的常量一致; 墙, 水, 森林和采石场的 spawn_weight 为 0, 调高它们才会生成.
+// 蓝图是 shelter (5 hp), house (1 hp, 3 木头, 1 石头), road 和 utility 的建筑;
+// 默认不划区, 没有预先铺好的路, 不模拟场也没有预算, 这些在 worlds/city.json 里打开.
+const DEFAULT_WORLD_JSON : &str = include_str!("../world_config.json");
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig::parse("world_config.json", DEFAULT_WORLD_JSON)
+            .unwrap_or_else(|err| panic!("bundled world_config.json is invalid: {}", err))
+    }
+}
+
+impl WorldConfig {
+    pub fn from_json(_path_name: String) -> Result<WorldConfig, WorldConfigError> {
+        let path = Path::new(_path_name.as_str());
+        let mut serialized : String = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut serialized))
+            .map_err(|err| WorldConfigError::Io(_path_name.clone(), err))?;
+        WorldConfig::parse(&_path_name, &serialized)
+    }
+
+    fn parse(_path_name: &str, serialized: &str) -> Result<WorldConfig, WorldConfigError> {
+        let config : WorldConfig = serde_json::from_str(serialized)
+            .map_err(|err| WorldConfigError::Malformed(_path_name.to_string(), err))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // 可以建造的环境模板, 按声明顺序; Animal.blueprint 是这里的下标.
+    pub fn blueprints(&self) -> Vec<EnvironmentTemplate> {
+        self.environments.iter().filter(|t| t.build_cost.is_some()).cloned().collect()
+    }
+
+    pub fn map_size(&self) -> (i32, i32) {
+        (self.width as i32, self.height as i32)
+    }
+
+    pub fn validate(&self) -> Result<(), WorldConfigError> {
+        if self.width == 0 || self.height == 0 || self.width > WIDTH || self.height > HEIGHT {
+            return Err(WorldConfigError::MapSize(self.width, self.height));
+        }
+        let total : u32 = self.environments.iter().map(|t| t.spawn_weight).sum();
+        if self.spawn_roll == 0 || total > self.spawn_roll {
+            return Err(WorldConfigError::SpawnRoll(total, self.spawn_roll));
+        }
+        let mut names : Vec<&String> = vec![];
+        for name in self.environments.iter().map(|t| &t.name).chain(self.animals.iter().map(|t| &t.name)) {
+            if names.contains(&name) {
+                return Err(WorldConfigError::DuplicateName(name.clone()));
+            }
+            names.push(name);
+        }
+        for t in &self.environments {
+            if t.hp <= 0 {
+                return Err(WorldConfigError::Template(t.name.clone(), "hp must be positive"));
+            }
+            if t.service_radius < 0 {
+                return Err(WorldConfigError::Template(t.name.clone(), "service_radius must not be negative"));
+            }
+            if t.price < 0 {
+                return Err(WorldConfigError::Template(t.name.clone(), "price must not be negative"));
+            }
+            if t.build_cost.is_some_and(|cost| cost < 0) {
+                return Err(WorldConfigError::Template(t.name.clone(), "build_cost must not be negative"));
+            }
+        }
+        for t in &self.animals {
+            if t.hp <= 0 {
+                return Err(WorldConfigError::Template(t.name.clone(), "hp must be positive"));
+            }
+            for pos in &t.spawn_points {
+                if pos.0 < 0 || pos.1 < 0 || pos.0 >= self.width as i32 || pos.1 >= self.height as i32 {
+                    return Err(WorldConfigError::SpawnPoint(t.name.clone(), *pos));
+                }
+            }
+        }
+        if self.animals.iter().all(|t| t.spawn_points.is_empty()) {
+            return Err(WorldConfigError::NoAnimals);
+        }
+        if let Some(r) = &self.reproduction {
+            if r.offspring_hp <= 0 {
+                return Err(WorldConfigError::Reproduction("offspring_hp must be positive"));
+            }
+            if r.hp_threshold <= r.offspring_hp {
+                return Err(WorldConfigError::Reproduction("hp_threshold must be above offspring_hp so the parent survives"));
+            }
+            if r.max_population == 0 {
+                return Err(WorldConfigError::Reproduction("max_population must be positive"));
+            }
+        }
+        if let Some(b) = &self.budget {
+            if b.level_width <= 0 {
+                return Err(WorldConfigError::Budget("level_width must be positive"));
+            }
+            if b.initial_treasury < b.bankruptcy_threshold {
+                return Err(WorldConfigError::Budget("initial_treasury must not be below bankruptcy_threshold"));
+            }
+        }
+        if let Some(c) = &self.fields {
+            if !(0.0..=1.0).contains(&c.diffusion) {
+                return Err(WorldConfigError::Fields("diffusion must be between 0 and 1"));
+            }
+            if !(0.0..=1.0).contains(&c.decay) {
+                return Err(WorldConfigError::Fields("decay must be between 0 and 1"));
+            }
+            if c.level_width.is_nan() || c.level_width <= 0.0 {
+                return Err(WorldConfigError::Fields("level_width must be positive"));
+            }
+        }
+        if let Some(r) = &self.roads {
+            if !self.environments.iter().any(|t| t.name == r.template && t.road) {
+                return Err(WorldConfigError::Roads(format!("template {:?} is not a road environment template", r.template)));
+            }
+            for (from, to) in &r.segments {
+                if from.0 != to.0 && from.1 != to.1 {
+                    return Err(WorldConfigError::Roads(format!("segment {:?} to {:?} is not a straight line", from, to)));
+                }
+            }
+            for pos in r.cells() {
+                if pos.0 < 0 || pos.1 < 0 || pos.0 >= self.width as i32 || pos.1 >= self.height as i32 {
+                    return Err(WorldConfigError::Roads(format!("cell {:?} is outside the map", pos)));
+                }
+            }
+        }
+        if let Some(z) = &self.zoning {
+            if z.growth_interval == 0 {
+                return Err(WorldConfigError::Zoning(String::from("growth_interval must be positive")));
+            }
+            for (zone, name) in &z.templates {
+                if !self.environments.iter().any(|t| &t.name == name) {
+                    return Err(WorldConfigError::Zoning(format!("{:?} template {:?} is not an environment template", zone, name)));
+                }
+            }
+            for area in &z.areas {
+                for pos in [area.from, area.to] {
+                    if pos.0 < 0 || pos.1 < 0 || pos.0 >= self.width as i32 || pos.1 >= self.height as i32 {
+                        return Err(WorldConfigError::Zoning(format!("{:?} area corner {:?} is outside the map", area.zone, pos)));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // 测试用的最小环境模板: 不生成, 1 hp, 不画出来, 也没有别的作用; 各个测试只改自己关心的字段.
+    pub(crate) fn blank_template(name : &str) -> EnvironmentTemplate {
+        EnvironmentTemplate {
+            name : String::from(name),
+            spawn_weight : 0,
+            tag : EnvironmentTag::SHELTER,
+            auto_interact : false,
+            hp : 1,
+            difficulty : 0,
+            penalty : 0,
+            reward : (0, 0),
+            yields : Inventory::default(),
+            color : (0, 0, 0, 0),
+            draw_type : DrawType::None,
+            d : 0,
+            blocking : false,
+            road : false,
+            requires_road : false,
+            produces : None,
+            conduit : None,
+            service_radius : 0,
+            consumes : vec![],
+            emits : vec![],
+            tax : 0,
+            upkeep : 0,
+            price : 0,
+            build_cost : None,
+            resource_cost : Inventory::default(),
+        }
+    }
+}
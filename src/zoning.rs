@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use serde_derive::{Serialize,Deserialize};
+
+use crate::simulation::{Environment, is_blocked};
+use crate::roads::RoadNetwork;
+use crate::world::{EnvironmentTemplate, WorldConfig};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Zone {
+    Residential,
+    Commercial,
+    Industrial,
+}
+
+pub const ZONES : [Zone; 3] = [Zone::Residential, Zone::Commercial, Zone::Industrial];
+
+// from 到 to (包含两端) 的矩形都划成 zone.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZoneArea {
+    pub zone : Zone,
+    pub from : (i32, i32),
+    pub to : (i32, i32),
+}
+
+impl ZoneArea {
+    pub fn contains(&self, pos : (i32, i32)) -> bool {
+        let (x0, x1) = (i32::min(self.from.0, self.to.0), i32::max(self.from.0, self.to.0));
+        let (y0, y1) = (i32::min(self.from.1, self.to.1), i32::max(self.from.1, self.to.1));
+        pos.0 >= x0 && pos.0 <= x1 && pos.1 >= y0 && pos.1 <= y1
+    }
+}
+
+// 每 growth_interval 个 tick, 需求为正的每种区各长出一栋建筑, 建筑用 templates 里对应名字的环境模板.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZoningConfig {
+    pub areas : Vec<ZoneArea>,
+    pub templates : BTreeMap<Zone, String>,
+    pub growth_interval : u32,
+    // 什么都还没有时住宅区的需求
+    pub base_demand : i32,
+}
+
+impl ZoningConfig {
+    pub fn zone_of(&self, pos : (i32, i32)) -> Option<Zone> {
+        // 重叠时后声明的区域优先
+        self.areas.iter().rev().find(|area| area.contains(pos)).map(|area| area.zone)
+    }
+}
+
+// 只算起作用 (接上了需要的路网和 utility) 的建筑.
+// 住宅要工作, 商业和工业要人:
+// 住宅需求 = base_demand + 商业 + 工业 - 住宅, 商业和工业的需求都是 住宅的一半 (向上取整) - 自己.
+pub fn zone_demand(ve : &[Environment], base_demand : i32) -> BTreeMap<Zone, i32> {
+    let mut counts : BTreeMap<Zone, i32> = ZONES.iter().map(|z| (*z, 0)).collect();
+    for e in ve {
+        if let (true, Some(zone)) = (e.alive && e.functioning(), e.zone) {
+            *counts.get_mut(&zone).unwrap() += 1;
+        }
+    }
+    let (r, c, i) = (counts[&Zone::Residential], counts[&Zone::Commercial], counts[&Zone::Industrial]);
+    let mut demand = BTreeMap::new();
+    demand.insert(Zone::Residential, base_demand + c + i - r);
+    demand.insert(Zone::Commercial, (r + 1) / 2 - c);
+    demand.insert(Zone::Industrial, (r + 1) / 2 - i);
+    demand
+}
+
+// 划了区, 在地图内, 没被挡住, 也没有活着的环境的格子, 按坐标排序;
+// 建筑需要路时只算接上路网的格子.
+fn vacant_cells(ve : &[Environment], config : &WorldConfig, zoning : &ZoningConfig, zone : Zone, template : &EnvironmentTemplate) -> Vec<(i32, i32)> {
+    let network = RoadNetwork::build(ve, config.map_size());
+    let mut cells = vec![];
+    for x in 0..config.width as i32 {
+        for y in 0..config.height as i32 {
+            if zoning.zone_of((x, y)) == Some(zone)
+                && (!template.requires_road || network.connected((x, y)))
+                && !is_blocked(ve, (x, y))
+                && !ve.iter().any(|e| e.alive && e.position == (x, y)) {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+// 在每个 tick 的末尾, garbage_collection 之后调用.
+pub fn grow_zones(ve : &mut Vec<Environment>, config : &WorldConfig, tick : u128, rng : &mut oorandom::Rand32) {
+    let zoning = match &config.zoning {
+        Some(zoning) if zoning.growth_interval > 0 => zoning,
+        _ => return,
+    };
+    if tick % zoning.growth_interval as u128 != 0 {
+        return;
+    }
+    let demand = zone_demand(ve, zoning.base_demand);
+    for zone in ZONES {
+        if demand[&zone] <= 0 {
+            continue;
+        }
+        let template = match zoning.templates.get(&zone).and_then(|name| config.environments.iter().find(|t| &t.name == name)) {
+            Some(template) => template,
+            None => continue,
+        };
+        let cells = vacant_cells(ve, config, zoning, zone, template);
+        if cells.is_empty() {
+            continue;
+        }
+        let pos = cells[rng.rand_range(0..cells.len() as u32) as usize];
+        let mut building = template.spwan(pos);
+        building.zone = Some(zone);
+        ve.push(building);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldConfigError;
+    use crate::world::tests::blank_template;
+
+    fn zoned(zone : Zone) -> Environment {
+        let mut e = blank_template("house").spwan((0, 0));
+        e.zone = Some(zone);
+        e
+    }
+
+    fn world(areas : Vec<ZoneArea>) -> WorldConfig {
+        WorldConfig {
+            environments : vec![blank_template("house")],
+            zoning : Some(ZoningConfig {
+                areas,
+                templates : [(Zone::Residential, String::from("house"))].into_iter().collect(),
+                growth_interval : 2,
+                base_demand : 1,
+            }),
+            ..WorldConfig::default()
+        }
+    }
+
+    #[test]
+    fn demand_counts_only_functioning_buildings() {
+        let demand = zone_demand(&[], 2);
+        assert_eq!((demand[&Zone::Residential], demand[&Zone::Commercial], demand[&Zone::Industrial]), (2, 0, 0));
+        let mut unconnected = zoned(Zone::Residential);
+        unconnected.requires_road = true;
+        let mut dead = zoned(Zone::Commercial);
+        dead.alive = false;
+        let ve = vec![zoned(Zone::Residential), zoned(Zone::Residential), zoned(Zone::Residential), zoned(Zone::Industrial), unconnected, dead];
+        let demand = zone_demand(&ve, 2);
+        assert_eq!((demand[&Zone::Residential], demand[&Zone::Commercial], demand[&Zone::Industrial]), (0, 2, 1));
+    }
+
+    #[test]
+    fn grows_inside_the_area_every_growth_interval_while_there_is_demand() {
+        let config = world(vec![ZoneArea { zone : Zone::Residential, from : (0, 0), to : (1, 0) }]);
+        let mut rng = oorandom::Rand32::new(1);
+        let mut ve = vec![];
+        grow_zones(&mut ve, &config, 1, &mut rng);
+        assert!(ve.is_empty());
+        grow_zones(&mut ve, &config, 2, &mut rng);
+        assert_eq!(ve.len(), 1);
+        assert_eq!(ve[0].zone, Some(Zone::Residential));
+        assert!(ve[0].position.0 <= 1);
+        // 住宅的需求已经满了, 商业区没有模板
+        grow_zones(&mut ve, &config, 4, &mut rng);
+        assert_eq!(ve.len(), 1);
+    }
+
+    #[test]
+    fn areas_must_lie_inside_the_map() {
+        let edge = WorldConfig::default().width as i32;
+        assert!(world(vec![ZoneArea { zone : Zone::Residential, from : (0, 0), to : (edge - 1, 0) }]).validate().is_ok());
+        let outside = world(vec![ZoneArea { zone : Zone::Industrial, from : (0, 0), to : (edge, 0) }]);
+        assert!(matches!(outside.validate(), Err(WorldConfigError::Zoning(_))));
+    }
+}